@@ -1,14 +1,35 @@
-use object::read::pe::{PeFile32, PeFile64};
+use forward_dll_pe::{
+    filter::{ExportAction, ExportFilter},
+    merge::{merge_exports, ConflictPolicy, MergeError, MergeOptions},
+    presets::Preset,
+    stub::{export_symbol_name, ordinal_lookup_name, ordinal_symbol_name, DYNAMIC_EXPORTS_FILE},
+    ExportItem,
+};
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
-use syn::{parse_macro_input, LitStr};
+use syn::{parse_macro_input, parse_quote, punctuated::Punctuated, FnArg, LitStr, Token};
 
 const FORWARD_ATTR_LACK_MESSAGE: &str =
-    r#"你需要添加 #[forward(target = "path/of/target_dll.dll")]"#;
+    r#"你需要添加 #[forward(target = "path/of/target_dll.dll")] 或 #[forward(preset = "version")]"#;
 const FORWARD_ATTR_INVALID_MESSAGE: &str = r#"#[forward()] 的参数格式错误，正确格式如 #[forward(target = "C:\Windows\System32\version.dll")]"#;
+const FORWARD_CONFLICT_INVALID_MESSAGE: &str =
+    r#"on_conflict 的值只能是 "error"、"first" 或 "last""#;
+const FORWARD_PRESET_INVALID_MESSAGE: &str =
+    r#"preset 的值只能是 "version"、"dinput8"、"d3d9"、"xinput1_3" 或 "dsound""#;
+const FORWARD_RUNTIME_PATH_INVALID_MESSAGE: &str = r#"runtime_path 应写在对应的 target 或 preset 之后，如 #[forward(target = "version.dll", runtime_path = "{system}\version.dll")]"#;
+const FORWARD_MISSING_INVALID_MESSAGE: &str = r#"on_missing 的值只能是 "exit"、"abort"、callback = 函数 或 return = 值，如 on_missing(return = 0)"#;
+const FORWARD_EXCLUDE_INVALID_MESSAGE: &str =
+    r#"exclude 的参数格式错误，正确格式如 exclude("GetFileVersionInfoW", "VerQueryValue*")"#;
+const FORWARD_BUILD_SCRIPT_LACK_MESSAGE: &str = "没有找到 build.rs 输出的导出信息，跳转函数不会被导出，请在 build.rs 中调用 forward_dll::derive_support::emit_exports 或 ForwardConfig::emit_dynamic";
+const HOOK_ATTR_INVALID_MESSAGE: &str = r#"#[hook()] 的参数格式错误，正确格式如 #[hook(module = VersionModule)] 或 #[hook(module = VersionModule, name = "GetFileVersionInfoW")]"#;
+const HOOK_FN_INVALID_MESSAGE: &str =
+    "#[hook] 只能用于普通函数，不能有 self 参数、泛型、可变参数或 async";
 
 /// ForwardModule 派生宏。用于读取 DLL 的导出表，生成用于转发的导出函数。
 ///
+/// 生成的函数只有内部符号名称，需要在 `build.rs` 中调用 `forward_dll::derive_support::emit_exports` 以目标中的名称导出，
+/// 没有调用时会产生编译错误。
+///
 /// # 使用方式：
 ///
 /// ```rust,ignore
@@ -18,6 +39,70 @@ const FORWARD_ATTR_INVALID_MESSAGE: &str = r#"#[forward()] 的参数格式错误
 /// #[forward(target = "C:\\Windows\\System32\\version.dll")]
 /// struct VersionModule;
 /// ```
+///
+/// 可以指定多个 `target`，把多个 DLL 的导出项合并到同一个代理 DLL 中。多个 DLL 存在同名导出项时默认报错，可以用 `on_conflict = "first"` 或 `on_conflict = "last"` 指定使用哪一个：
+///
+/// ```rust,ignore
+/// #[derive(ForwardModule)]
+/// #[forward(target = "core.dll", target = "core_ext.dll", on_conflict = "first")]
+/// struct CoreModule;
+/// ```
+///
+/// `target` 是编译时读取导出表的路径，运行时默认也从这个路径加载。用 `runtime_path` 指定运行时的路径，
+/// 可以包含 `{system}`、`{syswow64}`、`{proxy_dir}`、`{env:VAR}` 等占位符，写多个时按顺序尝试，
+/// 作用于前面最近的 `target` 或 `preset`，见 `forward_dll::TargetPath`：
+///
+/// ```rust,ignore
+/// #[derive(ForwardModule)]
+/// #[forward(
+///     target = "C:\\Windows\\System32\\version.dll",
+///     runtime_path = "{proxy_dir}\\version_orig.dll",
+///     runtime_path = "{system}\\version.dll"
+/// )]
+/// struct VersionModule;
+/// ```
+///
+/// 常见的系统 DLL 可以用 `preset` 代替 `target`，使用内置的导出表，编译时不需要读取 DLL，运行时从系统目录加载，
+/// 见 `forward_dll::presets`。`build.rs` 中对应地调用 `forward_dll::derive_support::emit_preset_exports`：
+///
+/// ```rust,ignore
+/// #[derive(ForwardModule)]
+/// #[forward(preset = "version")]
+/// struct VersionModule;
+/// ```
+///
+/// 用 `on_missing` 指定目标函数无法找到时的处理方式，见 `forward_dll::FailurePolicy`：
+///
+/// ```rust,ignore
+/// fn on_missing(name: &'static str) -> isize {
+///     eprintln!("{name} is missing");
+///     0
+/// }
+///
+/// #[derive(ForwardModule)]
+/// #[forward(target = "optional.dll", on_missing(callback = on_missing))]
+/// struct OptionalModule;
+///
+/// // 也可以是 on_missing = "exit"、on_missing = "abort" 或 on_missing(return = 0)。
+/// ```
+///
+/// 用 `trace` 让每个跳转函数在跳转之前记录导出项名称、线程 ID 与前 4 个参数，默认写入代理 DLL 旁边的文件，
+/// 可以用 `forward_dll::trace::set_sink` 替换：
+///
+/// ```rust,ignore
+/// #[derive(ForwardModule)]
+/// #[forward(target = "C:\\Windows\\System32\\version.dll", trace)]
+/// struct VersionModule;
+/// ```
+///
+/// 用 `exclude` 指定不转发的导出项，支持 `*` 与 `?` 通配符。这些导出项不会生成转发函数，需要自己用 `#[no_mangle]` 实现，
+/// `build.rs` 中的 `ForwardConfig` 也要设置同样的 `exclude`。它们仍可以通过 `ForwardModule::resolve` 取得目标函数，见 `hook`：
+///
+/// ```rust,ignore
+/// #[derive(ForwardModule)]
+/// #[forward(target = "C:\\Windows\\System32\\version.dll", exclude("GetFileVersionInfoW", "VerQueryValue*"))]
+/// struct VersionModule;
+/// ```
 #[proc_macro_derive(ForwardModule, attributes(forward))]
 pub fn derive_forward_module(item: TokenStream) -> TokenStream {
     let input = parse_macro_input!(item as syn::DeriveInput);
@@ -27,14 +112,83 @@ pub fn derive_forward_module(item: TokenStream) -> TokenStream {
         .find(|i| i.path().is_ident("forward"))
         .expect(FORWARD_ATTR_LACK_MESSAGE);
 
-    // 解析 #[forward(target = "")] 的参数。
-    let mut dll_path: Option<LitStr> = None;
+    // 解析 #[forward(target = "")] 的参数，可以有多个 target 或 preset。
+    let mut sources: Vec<Source> = Vec::new();
+    let mut on_conflict = ConflictPolicy::Error;
+    let mut exclude = Vec::new();
+    let mut failure_policy = quote! { Exit };
+    let mut trace = false;
     forward_attr
         .parse_nested_meta(|meta| {
             let path = &meta.path;
             if path.is_ident("target") {
                 let value = meta.value().expect(FORWARD_ATTR_INVALID_MESSAGE);
-                dll_path = Some(value.parse().expect(FORWARD_ATTR_INVALID_MESSAGE));
+                sources.push(Source {
+                    path: value.parse().expect(FORWARD_ATTR_INVALID_MESSAGE),
+                    preset: None,
+                    runtime_paths: Vec::new(),
+                });
+            } else if path.is_ident("preset") {
+                let value: LitStr = meta.value()?.parse()?;
+                let preset: Preset = value
+                    .value()
+                    .parse()
+                    .map_err(|_| meta.error(FORWARD_PRESET_INVALID_MESSAGE))?;
+                // 运行时默认从系统目录加载。
+                let path = format!("{{system}}\\{}", preset.dll_name());
+                sources.push(Source {
+                    path: LitStr::new(&path, value.span()),
+                    preset: Some(preset),
+                    runtime_paths: Vec::new(),
+                });
+            } else if path.is_ident("trace") {
+                trace = true;
+            } else if path.is_ident("runtime_path") {
+                let value: LitStr = meta.value()?.parse()?;
+                sources
+                    .last_mut()
+                    .ok_or_else(|| meta.error(FORWARD_RUNTIME_PATH_INVALID_MESSAGE))?
+                    .runtime_paths
+                    .push(value);
+            } else if path.is_ident("on_conflict") {
+                let value = meta.value().expect(FORWARD_ATTR_INVALID_MESSAGE);
+                let value: LitStr = value.parse().expect(FORWARD_CONFLICT_INVALID_MESSAGE);
+                on_conflict = match value.value().as_str() {
+                    "error" => ConflictPolicy::Error,
+                    "first" => ConflictPolicy::FirstWins,
+                    "last" => ConflictPolicy::LastWins,
+                    _ => return Err(meta.error(FORWARD_CONFLICT_INVALID_MESSAGE)),
+                };
+            } else if path.is_ident("on_missing") {
+                failure_policy = if meta.input.peek(Token![=]) {
+                    let value: LitStr = meta.value()?.parse()?;
+                    match value.value().as_str() {
+                        "exit" => quote! { Exit },
+                        "abort" => quote! { Abort },
+                        _ => return Err(meta.error(FORWARD_MISSING_INVALID_MESSAGE)),
+                    }
+                } else {
+                    let mut policy = None;
+                    meta.parse_nested_meta(|meta| {
+                        if meta.path.is_ident("callback") {
+                            let callback: syn::Path = meta.value()?.parse()?;
+                            policy = Some(quote! { Callback(#callback) });
+                        } else if meta.path.is_ident("return") {
+                            let value: syn::Expr = meta.value()?.parse()?;
+                            policy = Some(quote! { ReturnValue(#value) });
+                        } else {
+                            return Err(meta.error(FORWARD_MISSING_INVALID_MESSAGE));
+                        }
+                        Ok(())
+                    })?;
+                    policy.ok_or_else(|| meta.error(FORWARD_MISSING_INVALID_MESSAGE))?
+                };
+            } else if path.is_ident("exclude") {
+                let content;
+                syn::parenthesized!(content in meta.input);
+                let patterns = Punctuated::<LitStr, Token![,]>::parse_terminated(&content)
+                    .map_err(|_| meta.error(FORWARD_EXCLUDE_INVALID_MESSAGE))?;
+                exclude.extend(patterns.iter().map(LitStr::value));
             } else {
                 return Err(meta.error(FORWARD_ATTR_INVALID_MESSAGE));
             }
@@ -42,73 +196,411 @@ pub fn derive_forward_module(item: TokenStream) -> TokenStream {
         })
         .expect(FORWARD_ATTR_INVALID_MESSAGE);
 
-    let dll_path = dll_path.expect(FORWARD_ATTR_INVALID_MESSAGE);
-    let exports = get_dll_export_names(dll_path.value().as_str())
-        .expect("指定的 DLL 可能是一个无效的 PE 文件");
+    if sources.is_empty() {
+        panic!("{}", FORWARD_ATTR_INVALID_MESSAGE);
+    }
+    // 跳转函数只有内部符号，build.rs 没有输出链接参数时代理 DLL 不会导出任何函数。
+    if read_dynamic_exports().is_none() {
+        return syn::Error::new(input.ident.span(), FORWARD_BUILD_SCRIPT_LACK_MESSAGE)
+            .to_compile_error()
+            .into();
+    }
+    // 被排除的导出项由使用者自己实现，不生成转发函数。
+    let filter = ExportFilter {
+        include: Vec::new(),
+        exclude,
+    };
+    // 内置的导出表可能因架构而不同，分别取得 x86 与其他架构下的导出项，读取 DLL 时两者相同。
+    let mut x86_sets = Vec::new();
+    let mut sets = Vec::new();
+    for source in &sources {
+        let (x86_exports, exports) = match source.preset {
+            Some(preset) => (
+                preset.exports(IMAGE_FILE_MACHINE_I386).unwrap_or_default(),
+                preset.exports(IMAGE_FILE_MACHINE_AMD64).unwrap_or_default(),
+            ),
+            None => {
+                let exports = forward_dll_pe::read_exports_from_path(source.path.value())
+                    .expect("指定的 DLL 可能是一个无效的 PE 文件");
+                (exports.clone(), exports)
+            }
+        };
+        x86_sets.push(x86_exports);
+        sets.push(exports);
+    }
 
-    let export_names: Vec<_> = exports.iter().map(|(_, fn_name)| fn_name).collect();
-    let export_idents: Vec<_> = exports
-        .iter()
-        .map(|(_, fn_name)| format_ident!("{fn_name}"))
-        .collect();
-    let export_count = exports.len();
+    // 失败策略定义在模块外面，这样 callback 中的路径与使用者的代码在同一个作用域中。
     let struct_name = input.ident;
+    let policy_ident = format_ident!("__FORWARD_DLL_FAILURE_POLICY_{struct_name}");
+    let options = ModuleOptions {
+        struct_name: &struct_name,
+        policy_ident: &policy_ident,
+        on_conflict,
+        filter: &filter,
+        trace,
+    };
+
+    let modules = if x86_sets == sets {
+        generate_module(&sources, &sets, &options, quote! {})
+    } else {
+        generate_module(
+            &sources,
+            &x86_sets,
+            &options,
+            quote! { #[cfg(target_arch = "x86")] },
+        )
+        .and_then(|x86_module| {
+            let module = generate_module(
+                &sources,
+                &sets,
+                &options,
+                quote! { #[cfg(not(target_arch = "x86"))] },
+            )?;
+            Ok(quote! { #x86_module #module })
+        })
+    };
+    let modules = match modules {
+        Ok(modules) => modules,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
     let impl_code = quote! {
-        const _ : () = {
-            extern crate forward_dll as _forward_dll;
+        #[doc(hidden)]
+        #[allow(non_upper_case_globals)]
+        const #policy_ident: ::forward_dll::FailurePolicy = ::forward_dll::FailurePolicy::#failure_policy;
+
+        #modules
+    };
+    impl_code.into()
+}
 
-            static mut _FORWARDER: _forward_dll::DllForwarder<#export_count> = _forward_dll::DllForwarder {
-                initialized: false,
-                module_handle: 0,
-                lib_name: #dll_path,
-                target_functions_address: [0; #export_count],
-                target_function_names: [#(#export_names),*],
+const IMAGE_FILE_MACHINE_I386: u16 = 0x14c;
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+
+/// `#[forward(...)]` 中的一个转发目标。
+struct Source {
+    /// `target` 的路径，编译时读取导出表，也是默认的运行时路径；`preset` 时为系统目录下的路径。
+    path: LitStr,
+    preset: Option<Preset>,
+    /// 运行时的候选路径，为空时使用 `path`。
+    runtime_paths: Vec<LitStr>,
+}
+
+struct ModuleOptions<'a> {
+    struct_name: &'a syn::Ident,
+    policy_ident: &'a syn::Ident,
+    on_conflict: ConflictPolicy,
+    filter: &'a ExportFilter,
+    trace: bool,
+}
+
+/// 生成包含所有 DllForwarder、跳转函数与 ForwardModule 实现的模块，`sets` 与 `sources` 一一对应。
+fn generate_module(
+    sources: &[Source],
+    sets: &[Vec<ExportItem>],
+    options: &ModuleOptions,
+    cfg: proc_macro2::TokenStream,
+) -> syn::Result<proc_macro2::TokenStream> {
+    // 数据导出没有办法通过跳转函数转发，生成的跳转函数被当作数据读取时会导致崩溃。
+    for (source, exports) in sources.iter().zip(sets) {
+        let data_exports: Vec<_> = exports
+            .iter()
+            .filter(|item| item.is_data() && options.filter.action(item) == ExportAction::Forward)
+            .map(|item| {
+                item.name_lossy()
+                    .unwrap_or_else(|| format!("#{}", item.ordinal))
+            })
+            .collect();
+        if !data_exports.is_empty() {
+            let message = format!(
+                "目标 DLL 中的以下导出项是数据而不是函数，动态转发无法转发它们，请改用 build.rs 中的静态转发：{}",
+                data_exports.join(", ")
+            );
+            return Err(syn::Error::new(source.path.span(), message));
+        }
+    }
+
+    // 动态转发不设置序号，因此序号冲突时直接重新分配即可。
+    let merge_options = MergeOptions {
+        on_conflict: options.on_conflict,
+        prefer: Vec::new(),
+        remap_ordinals: true,
+    };
+    let exports = match merge_exports(sets, &merge_options) {
+        Ok(exports) => exports,
+        Err(MergeError::NameConflict {
+            name,
+            first,
+            second,
+        }) => {
+            let message = format!(
+                "{} 与 {} 都导出了 {name}，可以用 on_conflict = \"first\" 或 on_conflict = \"last\" 指定使用哪一个",
+                sources[first].path.value(),
+                sources[second].path.value()
+            );
+            return Err(syn::Error::new(sources[second].path.span(), message));
+        }
+        Err(err) => panic!("{}", err),
+    };
+    // 与 build.rs 中的 `ForwardConfig::emit_dynamic` 一样，先合并再排除，这样两边的冲突检查与重新分配的序号一致。
+    let exports: Vec<_> = exports
+        .into_iter()
+        .filter(|entry| options.filter.action(&entry.item) != ExportAction::Skip)
+        .collect();
+
+    // 跟踪模式下跳转函数会先记录调用，见 `forward_dll::trace`。
+    let mode = if options.trace {
+        quote! { @trace }
+    } else {
+        quote! {}
+    };
+    let policy_ident = options.policy_ident;
+
+    // 每个目标各自生成一个 DllForwarder。
+    let mut forwarder_idents = Vec::new();
+    let mut stub_counts = Vec::new();
+    let mut forwarders = Vec::new();
+    for (index, source) in sources.iter().enumerate() {
+        // 跳转函数使用内部符号，由 build.rs 中的 derive_support::emit_exports 以目标中的名称和序号导出。
+        // 仅以序号导出的导出项按序号查找目标函数。
+        // 被排除的导出项不生成跳转函数，只保留查找目标函数的位置，放在最后，`#[hook]` 通过它取得原函数。
+        let mut export_names = Vec::new();
+        let mut export_fns = Vec::new();
+        let mut ordinals = Vec::new();
+        let mut excluded = Vec::new();
+        for entry in exports.iter().filter(|entry| entry.source == index) {
+            let name = entry.item.name_lossy();
+            let symbol = match &name {
+                Some(name) => export_symbol_name(name),
+                None => ordinal_symbol_name(entry.item.ordinal),
             };
+            let name = name.unwrap_or_else(|| {
+                ordinal_lookup_name(entry.remapped_from.unwrap_or(entry.item.ordinal))
+            });
+            if options.filter.action(&entry.item) == ExportAction::Local {
+                excluded.push((name, entry.item.ordinal));
+                continue;
+            }
+            export_names.push(name);
+            ordinals.push(entry.item.ordinal);
+            let fn_ident = format_ident!("{symbol}");
+            export_fns.push(quote! { #fn_ident = #symbol });
+        }
+        stub_counts.push(export_fns.len());
+        for (name, ordinal) in excluded {
+            export_names.push(name);
+            ordinals.push(ordinal);
+        }
+        let export_count = export_names.len();
+        let forwarder_ident = format_ident!("_FORWARDER_{index}");
+        let dll_path = &source.path;
+        let target = match source.runtime_paths.as_slice() {
+            [] => quote! { _forward_dll::TargetPath::new(#dll_path) },
+            [runtime_path] => quote! { _forward_dll::TargetPath::new(#runtime_path) },
+            candidates => quote! { _forward_dll::TargetPath::candidates(&[#(#candidates),*]) },
+        };
+        forwarders.push(quote! {
+            static #forwarder_ident: _forward_dll::DllForwarder<#export_count> =
+                _forward_dll::DllForwarder::with_target(
+                    #target,
+                    [#(#export_names),*],
+                    super::#policy_ident,
+                )
+                .with_ordinals([#(#ordinals),*]);
 
-            _forward_dll::define_function!(#dll_path, _FORWARDER, 0, #(#export_idents)*);
+            _forward_dll::define_function!(#mode #dll_path, #forwarder_ident, 0, #(#export_fns)*);
+        });
+        forwarder_idents.push(forwarder_ident);
+    }
+
+    // 跳转函数的内部符号需要用 global_asm 定义，只能放在模块中，不能放在 const _ 块中。
+    let struct_name = options.struct_name;
+    let mod_name = format_ident!("__forward_dll_{struct_name}");
+    Ok(quote! {
+        #cfg
+        #[doc(hidden)]
+        #[allow(non_snake_case)]
+        mod #mod_name {
+            extern crate forward_dll as _forward_dll;
+
+            #(#forwarders)*
 
-            impl _forward_dll::ForwardModule for #struct_name {
+            impl _forward_dll::ForwardModule for super::#struct_name {
                 fn init(&self) -> _forward_dll::ForwardResult<()> {
-                    unsafe { _FORWARDER.forward_all() }
+                    // 有多个目标时，之前已经成功初始化的不需要再次初始化。
+                    #(
+                        if !#forwarder_idents.is_initialized() {
+                            #forwarder_idents.forward_all()?;
+                        }
+                    )*
+                    Ok(())
+                }
+
+                fn set_failure_policy(&self, failure_policy: _forward_dll::FailurePolicy) {
+                    #(#forwarder_idents.set_failure_policy(failure_policy);)*
+                }
+
+                fn stats(&self) -> Box<dyn Iterator<Item = _forward_dll::ExportStats> + '_> {
+                    // 被排除的导出项没有跳转函数，不计入。
+                    Box::new(
+                        ::core::iter::empty()
+                            #(.chain(#forwarder_idents.stats().take(#stub_counts)))*
+                    )
+                }
+
+                fn resolve(&self, name: &str) -> _forward_dll::ForwardResult<usize> {
+                    #(
+                        if let Some(index) = #forwarder_idents.position(name) {
+                            return #forwarder_idents.resolve(index);
+                        }
+                    )*
+                    Err(_forward_dll::ForwardError::proc_not_found())
+                }
+
+                fn handle_failure(&self, name: &'static str) -> isize {
+                    #(
+                        if let Some(index) = #forwarder_idents.position(name) {
+                            return #forwarder_idents.handle_failure(index);
+                        }
+                    )*
+                    let err = _forward_dll::ForwardError::proc_not_found();
+                    _FORWARDER_0
+                        .failure_policy()
+                        .handle(name, _FORWARDER_0.target(), Some(&err))
                 }
             }
-        };
-    };
-    impl_code.into()
+        }
+    })
 }
 
-fn get_dll_export_names(dll_path: &str) -> Result<Vec<(u32, String)>, String> {
-    let dll_file = std::fs::read(dll_path).map_err(|err| format!("Failed to read file: {err}"))?;
-    let in_data = dll_file.as_slice();
-
-    let kind = object::FileKind::parse(in_data).map_err(|err| format!("Invalid file: {err}"))?;
-    let exports = match kind {
-        object::FileKind::Pe32 => PeFile32::parse(in_data)
-            .map_err(|err| format!("Invalid pe file: {err}"))?
-            .export_table()
-            .map_err(|err| format!("Invalid pe file: {err}"))?
-            .ok_or_else(|| "No export table".to_string())?
-            .exports(),
-        object::FileKind::Pe64 => PeFile64::parse(in_data)
-            .map_err(|err| format!("Invalid pe file: {err}"))?
-            .export_table()
-            .map_err(|err| format!("Invalid pe file: {err}"))?
-            .ok_or_else(|| "No export table".to_string())?
-            .exports(),
-        _ => return Err("Invalid file".to_string()),
+/// 用自己的函数替换目标 DLL 中的同名导出函数，函数中可以通过 `original` 调用原函数。
+///
+/// `module` 为派生了 `ForwardModule` 的类型，`original` 的类型与被修饰的函数相同，
+/// 从对应的 `DllForwarder` 中取得，查找结果会被保存在每个函数自己的静态变量中。原函数不存在时，调用 `original`
+/// 会按照 `module` 的失败策略处理，与跳转函数相同。导出名称默认为函数名，可以用 `name` 指定。
+/// 没有写调用约定时使用 `extern "system"`。
+///
+/// 被替换的导出项需要写在 `#[forward(exclude(...))]` 中，并在 `build.rs` 中用 `ForwardConfig::exclude` 排除，
+/// 这样导出的是这个函数而不是跳转函数，`build.rs` 中忘记排除时会产生编译错误。
+///
+/// ```rust,ignore
+/// use forward_dll::ForwardModule;
+///
+/// #[derive(ForwardModule)]
+/// #[forward(target = "C:\\Windows\\System32\\version.dll", exclude("GetFileVersionInfoW"))]
+/// struct VersionModule;
+///
+/// #[forward_dll::hook(module = VersionModule)]
+/// fn GetFileVersionInfoW(path: PCWSTR, handle: u32, len: u32, data: *mut c_void) -> BOOL {
+///     println!("GetFileVersionInfoW called");
+///     original(path, handle, len, data)
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn hook(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut module: Option<syn::Path> = None;
+    let mut export_name: Option<LitStr> = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("module") {
+            module = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("name") {
+            export_name = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error(HOOK_ATTR_INVALID_MESSAGE));
+        }
+        Ok(())
+    });
+    parse_macro_input!(attr with parser);
+    let Some(module) = module else {
+        return syn::Error::new(
+            proc_macro::Span::call_site().into(),
+            HOOK_ATTR_INVALID_MESSAGE,
+        )
+        .to_compile_error()
+        .into();
+    };
+
+    let mut func = parse_macro_input!(item as syn::ItemFn);
+    let sig = &func.sig;
+    if !sig.generics.params.is_empty() || sig.variadic.is_some() || sig.asyncness.is_some() {
+        return syn::Error::new_spanned(sig, HOOK_FN_INVALID_MESSAGE)
+            .to_compile_error()
+            .into();
+    }
+    let mut arg_types = Vec::new();
+    for input in &sig.inputs {
+        match input {
+            FnArg::Typed(arg) => arg_types.push(arg.ty.clone()),
+            FnArg::Receiver(receiver) => {
+                return syn::Error::new_spanned(receiver, HOOK_FN_INVALID_MESSAGE)
+                    .to_compile_error()
+                    .into();
+            }
+        }
+    }
+
+    // 导出名称同时也是在目标 DLL 中查找原函数的名称。
+    let (export_attr, name) = match export_name {
+        Some(name) => (quote! { #[export_name = #name] }, name.value()),
+        None => (quote! { #[no_mangle] }, sig.ident.to_string()),
+    };
+    if is_dynamically_forwarded(&name) {
+        let message = format!(
+            "build.rs 中的 emit_dynamic 仍会把 {name} 导出为跳转函数，被 hook 的导出项需要用 ForwardConfig::exclude 排除"
+        );
+        return syn::Error::new_spanned(&sig.ident, message)
+            .to_compile_error()
+            .into();
     }
-    .map_err(|err| format!("Invalid file: {err}"))?;
-
-    let mut names = Vec::new();
-    for export_item in exports {
-        names.push((
-            export_item.ordinal,
-            export_item
-                .name
-                .map(String::from_utf8_lossy)
-                .map(String::from)
-                .unwrap_or_default(),
-        ));
+    let abi = sig
+        .abi
+        .clone()
+        .unwrap_or_else(|| parse_quote! { extern "system" });
+    let output = &sig.output;
+    let original_type = quote! { #abi fn(#(#arg_types),*) #output };
+    let body = &func.block.stmts;
+    // 原函数的地址只查找一次，找不到时改为调用 fallback，按 ForwardModule 的失败策略处理。
+    func.block = parse_quote! {{
+        #[allow(unused_variables)]
+        let original: #original_type = {
+            static ORIGINAL: ::core::sync::atomic::AtomicUsize =
+                ::core::sync::atomic::AtomicUsize::new(0);
+
+            extern "system" fn fallback() -> isize {
+                ::forward_dll::ForwardModule::handle_failure(&#module, #name)
+            }
+
+            let mut address = ORIGINAL.load(::core::sync::atomic::Ordering::Acquire);
+            if address == 0 {
+                address = ::forward_dll::ForwardModule::resolve(&#module, #name)
+                    .unwrap_or(fallback as usize);
+                ORIGINAL.store(address, ::core::sync::atomic::Ordering::Release);
+            }
+            unsafe { ::core::mem::transmute::<usize, #original_type>(address) }
+        };
+        #(#body)*
+    }};
+    func.sig.abi = Some(abi);
+    func.vis = parse_quote! { pub };
+
+    quote! {
+        #export_attr
+        #[allow(non_snake_case)]
+        #func
     }
-    Ok(names)
+    .into()
+}
+
+/// `build.rs` 中的 `ForwardConfig::emit_dynamic` 是否仍把 `name` 导出为跳转函数，这时导出的不是被 hook 的函数。
+/// 没有调用 `emit_dynamic` 时不检查。
+fn is_dynamically_forwarded(name: &str) -> bool {
+    read_dynamic_exports()
+        .map(|names| names.lines().any(|line| line == name))
+        .unwrap_or(false)
+}
+
+/// 读取 `build.rs` 中的 `ForwardConfig::emit_dynamic` 写入 `OUT_DIR` 的导出名称，没有调用过时为 `None`。
+fn read_dynamic_exports() -> Option<String> {
+    let out_dir = std::env::var("OUT_DIR").ok()?;
+    std::fs::read_to_string(std::path::Path::new(&out_dir).join(DYNAMIC_EXPORTS_FILE)).ok()
 }
@@ -0,0 +1,70 @@
+//! x86 上 C 符号名称修饰的处理。
+//!
+//! x86 上，导入方链接的符号带有 `_` 前缀（`__stdcall` 还带有 `@N` 后缀），而 DLL 的导出表中通常是不带修饰的名称，
+//! 生成 Import Library 时需要区分这两种情况。其他架构上符号与导出名称相同。
+
+/// 名称是否已经带有修饰，与 MSVC 的判断方式相同：以 `@` 或 `?` 开头，或者含有 `@`。
+///
+/// ```rust
+/// use forward_dll_pe::decorate::is_decorated;
+///
+/// assert!(is_decorated("_Func@12"));
+/// assert!(is_decorated("@Func@8"));
+/// assert!(is_decorated("??0Widget@@QAE@XZ"));
+/// assert!(!is_decorated("Func"));
+/// assert!(!is_decorated("_Func"));
+/// ```
+pub fn is_decorated(name: &str) -> bool {
+    name.starts_with('@') || name.starts_with('?') || name.contains('@')
+}
+
+/// Import Library 中的一项导入。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportSymbol {
+    /// 导入方链接时使用的符号名称。
+    pub symbol: String,
+    /// 加载时在目标 DLL 的导出表中查找的名称。
+    pub lookup: String,
+}
+
+/// 取得导出名称在 Import Library 中对应的符号名称与查找名称。
+///
+/// x86 上，未修饰的名称（如 `Func`）对应符号 `_Func`，加载时去掉前缀按 `Func` 查找；
+/// 已修饰的名称（如 `_Func@12`、`??0Widget@@QAE@XZ`）本身就是符号名称，加载时按原样查找。
+///
+/// ```rust
+/// use forward_dll_pe::decorate::{import_symbol, ImportSymbol};
+///
+/// let undecorated = import_symbol("Func", true);
+/// assert_eq!(undecorated.symbol, "_Func");
+/// assert_eq!(undecorated.lookup, "Func");
+///
+/// let stdcall = import_symbol("_Func@12", true);
+/// assert_eq!(stdcall.symbol, "_Func@12");
+/// assert_eq!(stdcall.lookup, "_Func@12");
+///
+/// // 以 `_` 开头但没有修饰的名称同样需要加上前缀。
+/// let underscore = import_symbol("_errno", true);
+/// assert_eq!(underscore.symbol, "__errno");
+/// assert_eq!(underscore.lookup, "_errno");
+///
+/// // 其他架构上不做处理。
+/// assert_eq!(
+///     import_symbol("_Func@12", false),
+///     ImportSymbol {
+///         symbol: "_Func@12".to_string(),
+///         lookup: "_Func@12".to_string(),
+///     }
+/// );
+/// ```
+pub fn import_symbol(export_name: &str, x86: bool) -> ImportSymbol {
+    let symbol = if x86 && !is_decorated(export_name) {
+        format!("_{export_name}")
+    } else {
+        export_name.to_string()
+    };
+    ImportSymbol {
+        symbol,
+        lookup: export_name.to_string(),
+    }
+}
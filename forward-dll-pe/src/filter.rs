@@ -0,0 +1,103 @@
+//! 按名称筛选需要转发的导出项，支持 `*` 与 `?` 通配符。
+
+use crate::ExportItem;
+
+/// 导出项的处理方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportAction {
+    /// 转发到目标 DLL。
+    Forward,
+    /// 不转发，保留序号并绑定到代理 DLL 自己的同名实现。
+    Local,
+    /// 不导出。
+    Skip,
+}
+
+/// 导出项的筛选规则。
+///
+/// `include` 为空时包含所有导出项，否则只包含匹配的导出项，其余的不导出；
+/// 匹配 `exclude` 的导出项不转发，由代理 DLL 自己实现。没有名称的导出项不受 `exclude` 影响。
+///
+/// 目标 DLL 导出的 `DllMain`（如 xinput1_3.dll）总是不导出，否则会与代理 DLL 自己的 `DllMain` 重名。
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl ExportFilter {
+    /// 判断导出项的处理方式。
+    pub fn action(&self, item: &ExportItem) -> ExportAction {
+        let name = match item.name.as_deref() {
+            Some(b"DllMain") => return ExportAction::Skip,
+            Some(name) => name,
+            None if self.include.is_empty() => return ExportAction::Forward,
+            None => return ExportAction::Skip,
+        };
+        if !self.include.is_empty()
+            && !self
+                .include
+                .iter()
+                .any(|pattern| matches_pattern(pattern, name))
+        {
+            ExportAction::Skip
+        } else if self
+            .exclude
+            .iter()
+            .any(|pattern| matches_pattern(pattern, name))
+        {
+            ExportAction::Local
+        } else {
+            ExportAction::Forward
+        }
+    }
+
+    /// 返回没有匹配到任何导出项的规则，通常是拼写错误。
+    pub fn unused_patterns<'a>(&'a self, exports: &[ExportItem]) -> Vec<&'a str> {
+        self.include
+            .iter()
+            .chain(&self.exclude)
+            .filter(|pattern| {
+                !exports.iter().any(|item| {
+                    item.name
+                        .as_deref()
+                        .map(|name| matches_pattern(pattern, name))
+                        .unwrap_or(false)
+                })
+            })
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// 判断名称是否匹配通配符，`*` 匹配任意个字符，`?` 匹配一个字符。
+///
+/// ```rust
+/// use forward_dll_pe::filter::matches_pattern;
+///
+/// assert!(matches_pattern("VerQueryValue*", b"VerQueryValueW"));
+/// assert!(matches_pattern("GetFileVersionInfo?", b"GetFileVersionInfoW"));
+/// assert!(!matches_pattern("GetFileVersionInfo?", b"GetFileVersionInfoExW"));
+/// ```
+pub fn matches_pattern(pattern: &str, name: &[u8]) -> bool {
+    let pattern = pattern.as_bytes();
+    let (mut p, mut n) = (0, 0);
+    // 最近一次遇到 `*` 时的位置，匹配失败时从这里回溯。
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
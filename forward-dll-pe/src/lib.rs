@@ -0,0 +1,307 @@
+//! forward-dll-pe 用于读取 PE 文件的导出表，`forward-dll` 与 `forward-dll-derive` 都通过它来获取目标 DLL 的导出信息。
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! let exports = forward_dll_pe::read_exports_from_path("C:\\Windows\\System32\\version.dll").unwrap();
+//! for item in exports {
+//!     println!("{} {:?}", item.ordinal, item.name_lossy());
+//! }
+//! ```
+
+pub mod decorate;
+pub mod filter;
+pub mod merge;
+pub mod presets;
+pub mod stub;
+
+use std::path::Path;
+
+use object::{
+    pe::IMAGE_SCN_MEM_EXECUTE,
+    read::pe::{ExportTarget, ImageNtHeaders, PeFile, PeFile32, PeFile64},
+    LittleEndian,
+};
+
+/// 导出项的类型。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportKind {
+    /// 导出项位于可执行的节中，通常是函数。
+    Code,
+    /// 导出项位于不可执行的节中，如全局变量、虚表等。
+    Data,
+}
+
+/// 导出表中的一项。
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExportItem {
+    /// 导出序号。
+    pub ordinal: u32,
+    /// 导出名称的原始字节，仅以序号导出时为 `None`。
+    pub name: Option<Vec<u8>>,
+    /// 导出地址表中记录的 RVA。对于转发项，这是转发字符串的 RVA。
+    pub rva: u32,
+    /// 转发字符串，如 `NTDLL.RtlAllocateHeap`、`NTDLL.#12`，非转发项为 `None`。
+    pub forwarder: Option<Vec<u8>>,
+    /// 导出项是代码还是数据。
+    pub kind: ExportKind,
+}
+
+impl ExportItem {
+    /// 构造一个不是转发项的代码导出项，`rva` 为 0，用于内置或手动指定的导出表。
+    pub fn code(ordinal: u32, name: Option<&str>) -> Self {
+        ExportItem {
+            ordinal,
+            name: name.map(|name| name.as_bytes().to_vec()),
+            rva: 0,
+            forwarder: None,
+            kind: ExportKind::Code,
+        }
+    }
+
+    /// 以字符串形式返回导出名称，无效的 UTF-8 字节会被替换。
+    pub fn name_lossy(&self) -> Option<String> {
+        self.name
+            .as_deref()
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+    }
+
+    /// 以字符串形式返回转发字符串，无效的 UTF-8 字节会被替换。
+    pub fn forwarder_lossy(&self) -> Option<String> {
+        self.forwarder
+            .as_deref()
+            .map(|forwarder| String::from_utf8_lossy(forwarder).into_owned())
+    }
+
+    /// 是否为数据导出。
+    pub fn is_data(&self) -> bool {
+        self.kind == ExportKind::Data
+    }
+}
+
+#[derive(Debug)]
+pub enum PeError {
+    /// 读取文件失败。
+    Io(std::io::Error),
+    /// 解析 PE 文件失败。
+    Parse(object::Error),
+    /// 不是 PE32 或 PE32+ 文件。
+    UnsupportedFile,
+    /// 文件中没有导出表。
+    NoExportTable,
+}
+
+impl std::fmt::Display for PeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            PeError::Io(ref err) => write!(f, "Failed to read file: {}", err),
+            PeError::Parse(ref err) => write!(f, "Invalid pe file: {}", err),
+            PeError::UnsupportedFile => write!(f, "Invalid file"),
+            PeError::NoExportTable => write!(f, "No export table"),
+        }
+    }
+}
+
+impl std::error::Error for PeError {}
+
+impl From<std::io::Error> for PeError {
+    fn from(err: std::io::Error) -> Self {
+        PeError::Io(err)
+    }
+}
+
+impl From<object::Error> for PeError {
+    fn from(err: object::Error) -> Self {
+        PeError::Parse(err)
+    }
+}
+
+pub type PeResult<T> = std::result::Result<T, PeError>;
+
+/// 读取指定路径的 DLL 的导出表。
+pub fn read_exports_from_path<P: AsRef<Path>>(path: P) -> PeResult<Vec<ExportItem>> {
+    let data = std::fs::read(path)?;
+    read_exports(data.as_slice())
+}
+
+/// 从内存中的 PE 文件读取导出表，返回的导出项按导出地址表的顺序排列。
+pub fn read_exports(data: &[u8]) -> PeResult<Vec<ExportItem>> {
+    match object::FileKind::parse(data)? {
+        object::FileKind::Pe32 => read_pe_exports(&PeFile32::parse(data)?),
+        object::FileKind::Pe64 => read_pe_exports(&PeFile64::parse(data)?),
+        _ => Err(PeError::UnsupportedFile),
+    }
+}
+
+/// 读取指定路径的 PE 文件头中的机器类型。
+pub fn read_machine_from_path<P: AsRef<Path>>(path: P) -> PeResult<u16> {
+    let data = std::fs::read(path)?;
+    read_machine(data.as_slice())
+}
+
+/// 从内存中的 PE 文件读取文件头中的机器类型，即 `IMAGE_FILE_MACHINE_*` 的值。
+pub fn read_machine(data: &[u8]) -> PeResult<u16> {
+    let machine = match object::FileKind::parse(data)? {
+        object::FileKind::Pe32 => PeFile32::parse(data)?.nt_headers().file_header().machine,
+        object::FileKind::Pe64 => PeFile64::parse(data)?.nt_headers().file_header().machine,
+        _ => return Err(PeError::UnsupportedFile),
+    };
+    Ok(machine.get(LittleEndian))
+}
+
+fn read_pe_exports<Pe: ImageNtHeaders>(file: &PeFile<Pe>) -> PeResult<Vec<ExportItem>> {
+    let export_table = file.export_table()?.ok_or(PeError::NoExportTable)?;
+    let sections = file.section_table();
+
+    let mut export_list = Vec::new();
+    for export_item in export_table.exports()? {
+        // 导出地址表中没有使用的序号，RVA 为 0，不是导出项。
+        if let ExportTarget::Address(0) = export_item.target {
+            continue;
+        }
+        let rva = export_table.address_by_ordinal(export_item.ordinal)?;
+        let (forwarder, kind) = match export_item.target {
+            ExportTarget::Address(address) => {
+                let executable = sections
+                    .section_containing(address)
+                    .map(|section| {
+                        section.characteristics.get(LittleEndian) & IMAGE_SCN_MEM_EXECUTE != 0
+                    })
+                    .unwrap_or(false);
+                let kind = if executable {
+                    ExportKind::Code
+                } else {
+                    ExportKind::Data
+                };
+                (None, kind)
+            }
+            // 转发项无法从当前文件判断类型，按代码处理。
+            _ => (
+                export_table.forward_string(rva)?.map(<[u8]>::to_vec),
+                ExportKind::Code,
+            ),
+        };
+        export_list.push(ExportItem {
+            ordinal: export_item.ordinal,
+            name: export_item.name.map(<[u8]>::to_vec),
+            rva,
+            forwarder,
+            kind,
+        });
+    }
+    Ok(export_list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT_RVA: u32 = 0x1000;
+    const RDATA_RVA: u32 = 0x2000;
+    const DATA_RVA: u32 = RDATA_RVA + 0x1f0;
+
+    fn put(buf: &mut [u8], offset: u32, bytes: &[u8]) {
+        let offset = offset as usize;
+        buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// 构造只有 `.text` 与 `.rdata` 两个节的 PE32+ 文件，`exports` 为从序号 1 开始的名称与 RVA，RVA 为 0 表示未使用的序号。
+    fn build_pe64(exports: &[(Option<&str>, u32)]) -> Vec<u8> {
+        let mut named: Vec<_> = exports
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (name, _))| name.map(|name| (name, index as u16)))
+            .collect();
+        named.sort();
+
+        let mut rdata = vec![0u8; 0x200];
+        let eat = 40;
+        let names = eat + 4 * exports.len() as u32;
+        let ordinals = names + 4 * named.len() as u32;
+        let mut strings = ordinals + 2 * named.len() as u32;
+        let mut add_string = |rdata: &mut Vec<u8>, value: &str| {
+            let rva = RDATA_RVA + strings;
+            put(rdata, strings, value.as_bytes());
+            strings += value.len() as u32 + 1;
+            rva
+        };
+        let dll_name = add_string(&mut rdata, "test.dll");
+        put(&mut rdata, 12, &dll_name.to_le_bytes());
+        put(&mut rdata, 16, &1u32.to_le_bytes());
+        put(&mut rdata, 20, &(exports.len() as u32).to_le_bytes());
+        put(&mut rdata, 24, &(named.len() as u32).to_le_bytes());
+        put(&mut rdata, 28, &(RDATA_RVA + eat).to_le_bytes());
+        put(&mut rdata, 32, &(RDATA_RVA + names).to_le_bytes());
+        put(&mut rdata, 36, &(RDATA_RVA + ordinals).to_le_bytes());
+        for (index, (_, rva)) in exports.iter().enumerate() {
+            put(&mut rdata, eat + 4 * index as u32, &rva.to_le_bytes());
+        }
+        for (index, (name, ordinal)) in named.iter().enumerate() {
+            let name = add_string(&mut rdata, name);
+            put(&mut rdata, names + 4 * index as u32, &name.to_le_bytes());
+            put(
+                &mut rdata,
+                ordinals + 2 * index as u32,
+                &ordinal.to_le_bytes(),
+            );
+        }
+
+        let mut data = vec![0u8; 0x400];
+        put(&mut data, 0, b"MZ");
+        put(&mut data, 0x3c, &0x80u32.to_le_bytes());
+        put(&mut data, 0x80, b"PE\0\0");
+        put(&mut data, 0x84, &0x8664u16.to_le_bytes());
+        put(&mut data, 0x86, &2u16.to_le_bytes());
+        put(&mut data, 0x94, &240u16.to_le_bytes());
+        put(&mut data, 0x96, &0x2022u16.to_le_bytes());
+        put(&mut data, 0x98, &0x20bu16.to_le_bytes());
+        put(&mut data, 0x98 + 32, &0x1000u32.to_le_bytes());
+        put(&mut data, 0x98 + 36, &0x200u32.to_le_bytes());
+        put(&mut data, 0x98 + 56, &0x3000u32.to_le_bytes());
+        put(&mut data, 0x98 + 60, &0x400u32.to_le_bytes());
+        put(&mut data, 0x98 + 108, &16u32.to_le_bytes());
+        put(&mut data, 0x98 + 112, &RDATA_RVA.to_le_bytes());
+        put(&mut data, 0x98 + 116, &strings.to_le_bytes());
+        let sections = [
+            (b".text\0\0\0", TEXT_RVA, 0x400u32, 0x6000_0020u32),
+            (b".rdata\0\0", RDATA_RVA, 0x600, 0x4000_0040),
+        ];
+        for (index, (name, rva, offset, characteristics)) in sections.iter().enumerate() {
+            let header = 0x188 + 40 * index as u32;
+            put(&mut data, header, *name);
+            put(&mut data, header + 8, &0x200u32.to_le_bytes());
+            put(&mut data, header + 12, &rva.to_le_bytes());
+            put(&mut data, header + 16, &0x200u32.to_le_bytes());
+            put(&mut data, header + 20, &offset.to_le_bytes());
+            put(&mut data, header + 36, &characteristics.to_le_bytes());
+        }
+        data.extend_from_slice(&[0xc3; 0x200]);
+        data.extend_from_slice(&rdata);
+        data
+    }
+
+    #[test]
+    fn classifies_code_and_data() {
+        let data = build_pe64(&[(Some("Func"), TEXT_RVA), (Some("g_Data"), DATA_RVA)]);
+        let exports = read_exports(&data).unwrap();
+        let kinds: Vec<_> = exports.iter().map(|item| item.kind).collect();
+        assert_eq!(kinds, [ExportKind::Code, ExportKind::Data]);
+        assert_eq!(read_machine(&data).unwrap(), 0x8664);
+    }
+
+    #[test]
+    fn skips_unused_ordinals() {
+        let data = build_pe64(&[
+            (Some("A"), TEXT_RVA),
+            (None, 0),
+            (None, 0),
+            (Some("D"), TEXT_RVA),
+            (None, TEXT_RVA),
+        ]);
+        let exports = read_exports(&data).unwrap();
+        let ordinals: Vec<_> = exports.iter().map(|item| item.ordinal).collect();
+        assert_eq!(ordinals, [1, 4, 5]);
+        assert!(exports.iter().all(|item| !item.is_data()));
+        assert_eq!(exports[2].name, None);
+    }
+}
@@ -0,0 +1,313 @@
+//! 合并多个目标 DLL 的导出项，用于让一个代理 DLL 同时转发多个 DLL。
+
+use std::collections::HashMap;
+
+use crate::ExportItem;
+
+/// 多个目标 DLL 中存在同名导出项时的处理方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// 报错。
+    #[default]
+    Error,
+    /// 使用先添加的目标中的导出项。
+    FirstWins,
+    /// 使用后添加的目标中的导出项。
+    LastWins,
+}
+
+/// 合并导出项的选项。
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    /// 同名导出项的默认处理方式。
+    pub on_conflict: ConflictPolicy,
+    /// 为指定名称的导出项指定使用哪个目标，第二个值为目标的下标，优先于 `on_conflict`。
+    pub prefer: Vec<(Vec<u8>, usize)>,
+    /// 序号冲突时，是否为后出现的导出项重新分配序号。为 `false` 时序号冲突会报错。
+    pub remap_ordinals: bool,
+}
+
+/// 合并后的导出项。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergedExport {
+    /// 导出项所属目标的下标。
+    pub source: usize,
+    /// 导出项，序号冲突被重新分配时，`ordinal` 为新的序号。
+    pub item: ExportItem,
+    /// 序号被重新分配时，原来的序号。
+    pub remapped_from: Option<u32>,
+}
+
+#[derive(Debug)]
+pub enum MergeError {
+    /// 两个目标都导出了同名的导出项。
+    NameConflict {
+        name: String,
+        first: usize,
+        second: usize,
+    },
+    /// 两个目标中不同的导出项使用了相同的序号。
+    OrdinalConflict {
+        ordinal: u32,
+        first: usize,
+        second: usize,
+    },
+}
+
+impl std::fmt::Display for MergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            MergeError::NameConflict {
+                ref name,
+                first,
+                second,
+            } => write!(
+                f,
+                "Export {} exists in both target {} and target {}",
+                name, first, second
+            ),
+            MergeError::OrdinalConflict {
+                ordinal,
+                first,
+                second,
+            } => write!(
+                f,
+                "Ordinal {} is used by both target {} and target {}",
+                ordinal, first, second
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+/// 合并多个目标的导出项，`sets` 的下标即为 `MergedExport::source`。
+///
+/// 先按名称处理同名导出项，再处理序号冲突。结果保持目标的添加顺序。
+pub fn merge_exports(
+    sets: &[Vec<ExportItem>],
+    options: &MergeOptions,
+) -> Result<Vec<MergedExport>, MergeError> {
+    let mut merged: Vec<MergedExport> = Vec::new();
+    let mut name_index: HashMap<&[u8], usize> = HashMap::new();
+
+    for (source, exports) in sets.iter().enumerate() {
+        for item in exports {
+            let entry = MergedExport {
+                source,
+                item: item.clone(),
+                remapped_from: None,
+            };
+            let name = match item.name.as_deref() {
+                Some(name) => name,
+                None => {
+                    merged.push(entry);
+                    continue;
+                }
+            };
+            let existing = match name_index.get(name) {
+                Some(&existing) => existing,
+                None => {
+                    name_index.insert(name, merged.len());
+                    merged.push(entry);
+                    continue;
+                }
+            };
+
+            let first = merged[existing].source;
+            let preferred = options
+                .prefer
+                .iter()
+                .find(|(prefer_name, prefer_source)| {
+                    prefer_name.as_slice() == name
+                        && (*prefer_source == first || *prefer_source == source)
+                })
+                .map(|(_, prefer_source)| *prefer_source);
+            let replace = match (preferred, options.on_conflict) {
+                (Some(preferred), _) => preferred == source,
+                (None, ConflictPolicy::FirstWins) => false,
+                (None, ConflictPolicy::LastWins) => true,
+                (None, ConflictPolicy::Error) => {
+                    return Err(MergeError::NameConflict {
+                        name: String::from_utf8_lossy(name).into_owned(),
+                        first,
+                        second: source,
+                    })
+                }
+            };
+            if replace {
+                merged[existing] = entry;
+            }
+        }
+    }
+
+    let mut next_ordinal = merged
+        .iter()
+        .map(|entry| entry.item.ordinal)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let mut ordinal_source: HashMap<u32, usize> = HashMap::new();
+    for entry in merged.iter_mut() {
+        let ordinal = entry.item.ordinal;
+        let first = match ordinal_source.get(&ordinal) {
+            Some(&first) => first,
+            None => {
+                ordinal_source.insert(ordinal, entry.source);
+                continue;
+            }
+        };
+        if !options.remap_ordinals {
+            return Err(MergeError::OrdinalConflict {
+                ordinal,
+                first,
+                second: entry.source,
+            });
+        }
+        entry.remapped_from = Some(ordinal);
+        entry.item.ordinal = next_ordinal;
+        ordinal_source.insert(next_ordinal, entry.source);
+        next_ordinal += 1;
+    }
+
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(merged: &[MergedExport]) -> Vec<(usize, u32, Option<String>, Option<u32>)> {
+        merged
+            .iter()
+            .map(|entry| {
+                (
+                    entry.source,
+                    entry.item.ordinal,
+                    entry.item.name_lossy(),
+                    entry.remapped_from,
+                )
+            })
+            .collect()
+    }
+
+    fn conflicting_sets() -> Vec<Vec<ExportItem>> {
+        vec![
+            vec![
+                ExportItem::code(1, Some("Init")),
+                ExportItem::code(2, Some("Run")),
+            ],
+            vec![
+                ExportItem::code(3, Some("Run")),
+                ExportItem::code(4, Some("Stop")),
+            ],
+        ]
+    }
+
+    #[test]
+    fn name_conflict_is_an_error_by_default() {
+        let err = merge_exports(&conflicting_sets(), &MergeOptions::default()).unwrap_err();
+        assert!(matches!(
+            err,
+            MergeError::NameConflict { ref name, first: 0, second: 1 } if name == "Run"
+        ));
+    }
+
+    #[test]
+    fn first_wins_keeps_the_first_target() {
+        let options = MergeOptions {
+            on_conflict: ConflictPolicy::FirstWins,
+            ..Default::default()
+        };
+        let merged = merge_exports(&conflicting_sets(), &options).unwrap();
+        assert_eq!(
+            summary(&merged),
+            [
+                (0, 1, Some("Init".to_string()), None),
+                (0, 2, Some("Run".to_string()), None),
+                (1, 4, Some("Stop".to_string()), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn last_wins_replaces_in_place() {
+        let options = MergeOptions {
+            on_conflict: ConflictPolicy::LastWins,
+            ..Default::default()
+        };
+        let merged = merge_exports(&conflicting_sets(), &options).unwrap();
+        assert_eq!(
+            summary(&merged),
+            [
+                (0, 1, Some("Init".to_string()), None),
+                (1, 3, Some("Run".to_string()), None),
+                (1, 4, Some("Stop".to_string()), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn prefer_overrides_on_conflict() {
+        let options = MergeOptions {
+            on_conflict: ConflictPolicy::LastWins,
+            prefer: vec![(b"Run".to_vec(), 0)],
+            ..Default::default()
+        };
+        let merged = merge_exports(&conflicting_sets(), &options).unwrap();
+        assert_eq!(merged[1].source, 0);
+        assert_eq!(merged[1].item.ordinal, 2);
+
+        // 没有 prefer 的同名导出项仍然按 on_conflict 处理。
+        let options = MergeOptions {
+            prefer: vec![(b"Run".to_vec(), 1)],
+            ..Default::default()
+        };
+        let merged = merge_exports(&conflicting_sets(), &options).unwrap();
+        assert_eq!(merged[1].source, 1);
+    }
+
+    #[test]
+    fn ordinal_conflict_is_an_error_without_remap() {
+        let sets = vec![
+            vec![ExportItem::code(1, Some("A"))],
+            vec![ExportItem::code(1, Some("B"))],
+        ];
+        let err = merge_exports(&sets, &MergeOptions::default()).unwrap_err();
+        assert!(matches!(
+            err,
+            MergeError::OrdinalConflict {
+                ordinal: 1,
+                first: 0,
+                second: 1
+            }
+        ));
+    }
+
+    #[test]
+    fn remap_ordinals_keeps_the_original_ordinal() {
+        let sets = vec![
+            vec![ExportItem::code(1, Some("A")), ExportItem::code(5, None)],
+            vec![
+                ExportItem::code(1, Some("B")),
+                ExportItem::code(5, None),
+                ExportItem::code(7, None),
+            ],
+        ];
+        let options = MergeOptions {
+            remap_ordinals: true,
+            ..Default::default()
+        };
+        let merged = merge_exports(&sets, &options).unwrap();
+        assert_eq!(
+            summary(&merged),
+            [
+                (0, 1, Some("A".to_string()), None),
+                (0, 5, None, None),
+                (1, 8, Some("B".to_string()), Some(1)),
+                (1, 9, None, Some(5)),
+                (1, 7, None, None),
+            ]
+        );
+    }
+}
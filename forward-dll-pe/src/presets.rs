@@ -0,0 +1,381 @@
+//! 常见系统 DLL 的导出表，构建时不需要读取 System32 中的文件，因此可以在没有 Windows 的机器上构建代理 DLL。
+//!
+//! 每个 DLL 的 x86 与 x64 导出表分别记录，名称与序号与 Windows 10 中的实际 DLL 一致。只收录序号确定的 DLL，
+//! 其余 DLL 请读取实际的文件。在 Windows 上运行 `cargo test -p forward-dll-pe` 会将导出表与 System32、SysWOW64
+//! 中的 DLL 对比，不一致时输出实际的导出表，可以直接替换下面的数据。
+//!
+//! winmm、d3d11、dxgi、winhttp 还没有收录，需要在 Windows 上运行
+//! `cargo test -p forward-dll-pe print_pending_tables -- --ignored --nocapture` 生成导出表后再加入。
+//!
+//! ```rust
+//! use forward_dll_pe::presets::Preset;
+//!
+//! let preset: Preset = "version".parse().unwrap();
+//! let exports = preset.exports(0x8664).unwrap();
+//! assert_eq!(exports[0].ordinal, 1);
+//! assert_eq!(exports[0].name.as_deref(), Some(&b"GetFileVersionInfoA"[..]));
+//! ```
+
+use object::pe::{IMAGE_FILE_MACHINE_AMD64, IMAGE_FILE_MACHINE_I386};
+
+use crate::ExportItem;
+
+/// 内置导出表的 DLL。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Preset {
+    Version,
+    Dinput8,
+    D3d9,
+    Xinput1_3,
+    Dsound,
+}
+
+impl Preset {
+    /// 所有内置的 DLL。
+    pub const ALL: &'static [Preset] = &[
+        Preset::Version,
+        Preset::Dinput8,
+        Preset::D3d9,
+        Preset::Xinput1_3,
+        Preset::Dsound,
+    ];
+
+    /// 名称，如 `version`。
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Version => "version",
+            Preset::Dinput8 => "dinput8",
+            Preset::D3d9 => "d3d9",
+            Preset::Xinput1_3 => "xinput1_3",
+            Preset::Dsound => "dsound",
+        }
+    }
+
+    /// 文件名，如 `version.dll`。
+    pub fn dll_name(&self) -> String {
+        format!("{}.dll", self.name())
+    }
+
+    /// 指定架构（`IMAGE_FILE_MACHINE_*`）下的导出项，按序号排列，只支持 x86 与 x64。
+    pub fn exports(&self, machine: u16) -> Option<Vec<ExportItem>> {
+        Some(
+            self.table(machine)?
+                .iter()
+                .map(|(ordinal, name)| ExportItem::code(*ordinal, *name))
+                .collect(),
+        )
+    }
+
+    /// 序号与名称，名称为 `None` 表示仅以序号导出。
+    fn table(&self, machine: u16) -> Option<Table> {
+        let table = match (self, machine) {
+            (Preset::Version, IMAGE_FILE_MACHINE_I386) => VERSION_X86,
+            (Preset::Version, IMAGE_FILE_MACHINE_AMD64) => VERSION_X64,
+            (Preset::Dinput8, IMAGE_FILE_MACHINE_I386) => DINPUT8_X86,
+            (Preset::Dinput8, IMAGE_FILE_MACHINE_AMD64) => DINPUT8_X64,
+            (Preset::D3d9, IMAGE_FILE_MACHINE_I386) => D3D9_X86,
+            (Preset::D3d9, IMAGE_FILE_MACHINE_AMD64) => D3D9_X64,
+            (Preset::Xinput1_3, IMAGE_FILE_MACHINE_I386) => XINPUT1_3_X86,
+            (Preset::Xinput1_3, IMAGE_FILE_MACHINE_AMD64) => XINPUT1_3_X64,
+            (Preset::Dsound, IMAGE_FILE_MACHINE_I386) => DSOUND_X86,
+            (Preset::Dsound, IMAGE_FILE_MACHINE_AMD64) => DSOUND_X64,
+            _ => return None,
+        };
+        Some(table)
+    }
+}
+
+impl std::str::FromStr for Preset {
+    type Err = String;
+
+    /// 不区分大小写，可以带 `.dll` 后缀。
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let lower = name.to_ascii_lowercase();
+        let lower = lower.strip_suffix(".dll").unwrap_or(&lower);
+        Preset::ALL
+            .iter()
+            .copied()
+            .find(|preset| preset.name() == lower)
+            .ok_or_else(|| {
+                let names: Vec<_> = Preset::ALL.iter().map(Preset::name).collect();
+                format!(
+                    "Unknown preset {name}, available presets: {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// 按序号排列的序号与名称。
+type Table = &'static [(u32, Option<&'static str>)];
+
+const VERSION_X86: Table = &[
+    (1, Some("GetFileVersionInfoA")),
+    (2, Some("GetFileVersionInfoByHandle")),
+    (3, Some("GetFileVersionInfoExA")),
+    (4, Some("GetFileVersionInfoExW")),
+    (5, Some("GetFileVersionInfoSizeA")),
+    (6, Some("GetFileVersionInfoSizeExA")),
+    (7, Some("GetFileVersionInfoSizeExW")),
+    (8, Some("GetFileVersionInfoSizeW")),
+    (9, Some("GetFileVersionInfoW")),
+    (10, Some("VerFindFileA")),
+    (11, Some("VerFindFileW")),
+    (12, Some("VerInstallFileA")),
+    (13, Some("VerInstallFileW")),
+    (14, Some("VerLanguageNameA")),
+    (15, Some("VerLanguageNameW")),
+    (16, Some("VerQueryValueA")),
+    (17, Some("VerQueryValueW")),
+];
+
+const VERSION_X64: Table = &[
+    (1, Some("GetFileVersionInfoA")),
+    (2, Some("GetFileVersionInfoByHandle")),
+    (3, Some("GetFileVersionInfoExA")),
+    (4, Some("GetFileVersionInfoExW")),
+    (5, Some("GetFileVersionInfoSizeA")),
+    (6, Some("GetFileVersionInfoSizeExA")),
+    (7, Some("GetFileVersionInfoSizeExW")),
+    (8, Some("GetFileVersionInfoSizeW")),
+    (9, Some("GetFileVersionInfoW")),
+    (10, Some("VerFindFileA")),
+    (11, Some("VerFindFileW")),
+    (12, Some("VerInstallFileA")),
+    (13, Some("VerInstallFileW")),
+    (14, Some("VerLanguageNameA")),
+    (15, Some("VerLanguageNameW")),
+    (16, Some("VerQueryValueA")),
+    (17, Some("VerQueryValueW")),
+];
+
+const DINPUT8_X86: Table = &[
+    (1, Some("DirectInput8Create")),
+    (2, Some("DllCanUnloadNow")),
+    (3, Some("DllGetClassObject")),
+    (4, Some("DllRegisterServer")),
+    (5, Some("DllUnregisterServer")),
+    (6, Some("GetdfDIJoystick")),
+];
+
+const DINPUT8_X64: Table = &[
+    (1, Some("DirectInput8Create")),
+    (2, Some("DllCanUnloadNow")),
+    (3, Some("DllGetClassObject")),
+    (4, Some("DllRegisterServer")),
+    (5, Some("DllUnregisterServer")),
+    (6, Some("GetdfDIJoystick")),
+];
+
+const D3D9_X86: Table = &[
+    (16, Some("Direct3DShaderValidatorCreate9")),
+    (17, Some("PSGPError")),
+    (18, Some("PSGPSampleTexture")),
+    (19, Some("D3DPERF_BeginEvent")),
+    (20, Some("D3DPERF_EndEvent")),
+    (21, Some("D3DPERF_GetStatus")),
+    (22, Some("D3DPERF_QueryRepeatFrame")),
+    (23, Some("D3DPERF_SetMarker")),
+    (24, Some("D3DPERF_SetOptions")),
+    (25, Some("D3DPERF_SetRegion")),
+    (26, Some("DebugSetLevel")),
+    (27, Some("DebugSetMute")),
+    (28, Some("Direct3D9EnableMaximizedWindowedModeShim")),
+    (29, Some("Direct3DCreate9")),
+    (30, Some("Direct3DCreate9Ex")),
+    (31, Some("Direct3DCreate9On12")),
+    (32, Some("Direct3DCreate9On12Ex")),
+];
+
+const D3D9_X64: Table = &[
+    (16, Some("Direct3DShaderValidatorCreate9")),
+    (17, Some("PSGPError")),
+    (18, Some("PSGPSampleTexture")),
+    (19, Some("D3DPERF_BeginEvent")),
+    (20, Some("D3DPERF_EndEvent")),
+    (21, Some("D3DPERF_GetStatus")),
+    (22, Some("D3DPERF_QueryRepeatFrame")),
+    (23, Some("D3DPERF_SetMarker")),
+    (24, Some("D3DPERF_SetOptions")),
+    (25, Some("D3DPERF_SetRegion")),
+    (26, Some("DebugSetLevel")),
+    (27, Some("DebugSetMute")),
+    (28, Some("Direct3D9EnableMaximizedWindowedModeShim")),
+    (29, Some("Direct3DCreate9")),
+    (30, Some("Direct3DCreate9Ex")),
+    (31, Some("Direct3DCreate9On12")),
+    (32, Some("Direct3DCreate9On12Ex")),
+];
+
+const XINPUT1_3_X86: Table = &[
+    (1, Some("DllMain")),
+    (2, Some("XInputGetState")),
+    (3, Some("XInputSetState")),
+    (4, Some("XInputGetCapabilities")),
+    (5, Some("XInputEnable")),
+    (6, Some("XInputGetDSoundAudioDeviceGuids")),
+    (7, Some("XInputGetBatteryInformation")),
+    (8, Some("XInputGetKeystroke")),
+    // XInputGetStateEx、XInputWaitForGuideButton、XInputCancelGuideButtonWait、XInputPowerOffController。
+    (100, None),
+    (101, None),
+    (102, None),
+    (103, None),
+];
+
+const XINPUT1_3_X64: Table = &[
+    (1, Some("DllMain")),
+    (2, Some("XInputGetState")),
+    (3, Some("XInputSetState")),
+    (4, Some("XInputGetCapabilities")),
+    (5, Some("XInputEnable")),
+    (6, Some("XInputGetDSoundAudioDeviceGuids")),
+    (7, Some("XInputGetBatteryInformation")),
+    (8, Some("XInputGetKeystroke")),
+    // XInputGetStateEx、XInputWaitForGuideButton、XInputCancelGuideButtonWait、XInputPowerOffController。
+    (100, None),
+    (101, None),
+    (102, None),
+    (103, None),
+];
+
+const DSOUND_X86: Table = &[
+    (1, Some("DirectSoundCreate")),
+    (2, Some("DirectSoundEnumerateA")),
+    (3, Some("DirectSoundEnumerateW")),
+    (4, Some("DllCanUnloadNow")),
+    (5, Some("DllGetClassObject")),
+    (6, Some("DirectSoundCaptureCreate")),
+    (7, Some("DirectSoundCaptureEnumerateA")),
+    (8, Some("DirectSoundCaptureEnumerateW")),
+    (9, Some("GetDeviceID")),
+    (10, Some("DirectSoundFullDuplexCreate")),
+    (11, Some("DirectSoundCreate8")),
+    (12, Some("DirectSoundCaptureCreate8")),
+];
+
+const DSOUND_X64: Table = &[
+    (1, Some("DirectSoundCreate")),
+    (2, Some("DirectSoundEnumerateA")),
+    (3, Some("DirectSoundEnumerateW")),
+    (4, Some("DllCanUnloadNow")),
+    (5, Some("DllGetClassObject")),
+    (6, Some("DirectSoundCaptureCreate")),
+    (7, Some("DirectSoundCaptureEnumerateA")),
+    (8, Some("DirectSoundCaptureEnumerateW")),
+    (9, Some("GetDeviceID")),
+    (10, Some("DirectSoundFullDuplexCreate")),
+    (11, Some("DirectSoundCreate8")),
+    (12, Some("DirectSoundCaptureCreate8")),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_are_sorted_and_unique() {
+        for preset in Preset::ALL {
+            for machine in [IMAGE_FILE_MACHINE_I386, IMAGE_FILE_MACHINE_AMD64] {
+                let table = preset.table(machine).unwrap();
+                assert!(
+                    table.windows(2).all(|pair| pair[0].0 < pair[1].0),
+                    "{} {machine:#x}",
+                    preset.name()
+                );
+                let mut names: Vec<_> = table.iter().filter_map(|(_, name)| *name).collect();
+                names.sort_unstable();
+                names.dedup();
+                assert_eq!(
+                    names.len(),
+                    table.iter().filter(|(_, name)| name.is_some()).count()
+                );
+            }
+        }
+        assert!(Preset::Version.exports(0xaa64).is_none());
+    }
+
+    /// 序号与名称，与导出表数据的格式一致。
+    #[cfg(windows)]
+    type ActualTable = Vec<(u32, Option<String>)>;
+
+    /// 读取 System32 或 SysWOW64 中的 DLL，返回路径、架构与导出表，文件不存在时返回 `None`。
+    #[cfg(windows)]
+    fn read_system_dll(
+        dir: &str,
+        dll_name: &str,
+    ) -> Option<(std::path::PathBuf, u16, ActualTable)> {
+        let root = std::env::var("SystemRoot").unwrap_or_else(|_| "C:\\Windows".to_string());
+        let path = std::path::Path::new(&root).join(dir).join(dll_name);
+        let data = std::fs::read(&path).ok()?;
+        let machine = crate::read_machine(&data).unwrap();
+        let exports = crate::read_exports(&data)
+            .unwrap()
+            .iter()
+            .map(|item| (item.ordinal, item.name_lossy()))
+            .collect();
+        Some((path, machine, exports))
+    }
+
+    /// 按下面数据的格式输出导出表。
+    #[cfg(windows)]
+    fn format_table(exports: &[(u32, Option<String>)]) -> String {
+        exports
+            .iter()
+            .map(|(ordinal, name)| format!("    ({ordinal}, {name:?}),\n"))
+            .collect()
+    }
+
+    /// 与 System32、SysWOW64 中实际的 DLL 对比，没有安装的 DLL（如 xinput1_3）会被跳过。
+    /// 不一致时输出实际的导出表，可以直接替换对应的数据。
+    #[cfg(windows)]
+    #[test]
+    fn tables_match_system_dlls() {
+        for dir in ["System32", "SysWOW64"] {
+            for preset in Preset::ALL {
+                let Some((path, machine, actual)) = read_system_dll(dir, &preset.dll_name()) else {
+                    continue;
+                };
+                let expected: Vec<_> = preset
+                    .table(machine)
+                    .unwrap()
+                    .iter()
+                    .map(|(ordinal, name)| (*ordinal, name.map(str::to_string)))
+                    .collect();
+                assert_eq!(
+                    expected,
+                    actual,
+                    "{}:\n{}",
+                    path.display(),
+                    format_table(&actual)
+                );
+            }
+        }
+    }
+
+    /// 输出还没有收录的 DLL 的导出表，用于添加新的内置导出表。
+    #[cfg(windows)]
+    #[test]
+    #[ignore]
+    fn print_pending_tables() {
+        for dir in ["System32", "SysWOW64"] {
+            for name in ["winmm", "d3d11", "dxgi", "winhttp"] {
+                let Some((path, machine, exports)) = read_system_dll(dir, &format!("{name}.dll"))
+                else {
+                    continue;
+                };
+                let arch = if machine == IMAGE_FILE_MACHINE_I386 {
+                    "X86"
+                } else {
+                    "X64"
+                };
+                println!(
+                    "// {}\nconst {}_{arch}: Table = &[\n{}];\n",
+                    path.display(),
+                    name.to_ascii_uppercase(),
+                    format_table(&exports)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! 动态转发生成的导出函数的命名规则，过程宏与 `build.rs` 中的辅助函数需要保持一致。
+
+/// 仅以序号导出的导出项在链接参数中使用的名称，如序号 `3` 对应 `forward_dll_ordinal_3`。
+///
+/// 这些导出项在链接时以 `NONAME` 导出，名称不会出现在代理 DLL 的导出表中。
+pub fn ordinal_stub_name(ordinal: u32) -> String {
+    format!("forward_dll_ordinal_{ordinal}")
+}
+
+/// 按序号查找目标函数时使用的名称，如序号 `3` 对应 `#3`，`ForeignLibrary::get_proc_address` 会按序号查找以 `#` 开头的名称。
+pub fn ordinal_lookup_name(ordinal: u32) -> String {
+    format!("#{ordinal}")
+}
+
+/// 导出项对应的跳转函数的内部符号名称，同时也是合法的 Rust 标识符。
+///
+/// 字母和数字保持不变，其余字符（包括 `_`）替换为 `_` 加两位十六进制数，因此不同的导出名称不会得到相同的符号，
+/// 如 `memcpy` 对应 `__forward_dll_export_memcpy`，`??0Widget@@QAE@XZ` 对应 `__forward_dll_export__3f_3f0Widget_40_40QAE_40XZ`。
+pub fn export_symbol_name(export_name: &str) -> String {
+    let mut symbol = String::from("__forward_dll_export_");
+    for byte in export_name.bytes() {
+        if byte.is_ascii_alphanumeric() {
+            symbol.push(byte as char);
+        } else {
+            symbol.push_str(&format!("_{byte:02x}"));
+        }
+    }
+    symbol
+}
+
+/// `OUT_DIR` 下记录转发到跳转函数的导出名称的文件，每行一个名称。
+///
+/// 由 `build.rs` 中的 `ForwardConfig::emit_dynamic` 写入，`#[hook]` 读取它检查被替换的导出项是否已经排除。
+pub const DYNAMIC_EXPORTS_FILE: &str = "forward_dll_dynamic_exports.txt";
+
+/// 仅以序号导出的导出项对应的跳转函数的内部符号名称，如序号 `3` 对应 `__forward_dll_ordinal_3`。
+pub fn ordinal_symbol_name(ordinal: u32) -> String {
+    format!("__forward_dll_ordinal_{ordinal}")
+}
+
+/// CRT 与 Rust 自身会定义的常见符号。以这些名称直接定义导出函数会产生重复符号，甚至替换掉代理 DLL 自己使用的实现。
+const RUNTIME_SYMBOLS: &[&str] = &[
+    "memcpy",
+    "memmove",
+    "memset",
+    "memcmp",
+    "memchr",
+    "bcmp",
+    "strlen",
+    "strcmp",
+    "strncmp",
+    "strcpy",
+    "strncpy",
+    "strcat",
+    "strchr",
+    "strrchr",
+    "strstr",
+    "wcslen",
+    "wcscmp",
+    "wcscpy",
+    "malloc",
+    "calloc",
+    "realloc",
+    "free",
+    "abort",
+    "exit",
+    "_exit",
+    "atexit",
+    "printf",
+    "sprintf",
+    "snprintf",
+    "fprintf",
+    "fwrite",
+    "puts",
+    "qsort",
+    "_errno",
+    "_fltused",
+    "__chkstk",
+    "___chkstk_ms",
+    "__CxxFrameHandler3",
+    "__C_specific_handler",
+    "__security_check_cookie",
+    "DllMain",
+    "_DllMainCRTStartup",
+    "rust_begin_unwind",
+    "rust_eh_personality",
+];
+
+/// 判断导出名称是否与 CRT 或 Rust 自身的符号重名，见 [`export_symbol_name`]。
+///
+/// ```rust
+/// use forward_dll_pe::stub::is_runtime_symbol;
+///
+/// assert!(is_runtime_symbol("memcpy"));
+/// assert!(is_runtime_symbol("__rust_alloc"));
+/// assert!(!is_runtime_symbol("GetFileVersionInfoW"));
+/// ```
+pub fn is_runtime_symbol(export_name: &str) -> bool {
+    RUNTIME_SYMBOLS.contains(&export_name) || export_name.starts_with("__rust_")
+}
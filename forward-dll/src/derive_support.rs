@@ -0,0 +1,43 @@
+//! 在 `build.rs` 中配合 `#[derive(ForwardModule)]` 使用的辅助函数。
+//!
+//! 过程宏生成的跳转函数只有内部符号名称，既不会以目标中的名称导出，也没有办法设置导出序号，
+//! 需要在 `build.rs` 中输出链接参数，以目标中的名称和序号导出它们。
+
+use crate::{ForwardConfig, Preset};
+
+/// 以目标 `DLL` 中的名称和序号导出 `#[derive(ForwardModule)]` 生成的跳转函数，
+/// 这样按序号导入的程序也可以使用动态转发的代理 DLL。仅以序号导出的导出项会以 `NONAME` 导出。
+///
+/// `dll_path` 应与 `#[forward(target = "...")]` 中的路径相同。有多个 `target` 或使用了 `exclude` 时，
+/// 请使用 [`ForwardConfig::emit_dynamic`]。
+///
+/// # Example
+///
+/// 在 `build.rs` 中添加如下代码：
+///
+/// ```rust,no_run
+/// forward_dll::derive_support::emit_exports("C:\\Windows\\system32\\version.dll").unwrap();
+/// ```
+pub fn emit_exports(dll_path: &str) -> Result<(), String> {
+    ForwardConfig::new().target(dll_path).emit_dynamic()
+}
+
+/// 同 [`emit_exports`]，用于 `#[forward(preset = "...")]`，导出表是内置的，不需要读取目标 `DLL`。
+///
+/// ```rust,no_run
+/// use forward_dll::Preset;
+///
+/// forward_dll::derive_support::emit_preset_exports(Preset::Version).unwrap();
+/// ```
+pub fn emit_preset_exports(preset: Preset) -> Result<(), String> {
+    ForwardConfig::new()
+        .target(&preset.dll_name())
+        .preset(preset)
+        .emit_dynamic()
+}
+
+/// 旧的名称，同 [`emit_exports`]。
+#[deprecated(note = "use emit_exports instead")]
+pub fn emit_ordinals(dll_path: &str) -> Result<(), String> {
+    emit_exports(dll_path)
+}
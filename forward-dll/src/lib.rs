@@ -16,7 +16,13 @@
 //!
 //! 这种方式是在运行时动态加载目标 `DLL`，然后在导出的函数中，跳转到目标 `DLL` 的地址。
 //!
-//! ```rust
+//! 生成的跳转函数需要在 `build.rs` 中导出，没有导出时会产生编译错误：
+//!
+//! ```rust,no_run
+//! forward_dll::derive_support::emit_exports("C:\\Windows\\system32\\version.dll").unwrap();
+//! ```
+//!
+//! ```rust,ignore
 //! use forward_dll::ForwardModule;
 //!
 //! #[derive(ForwardModule)]
@@ -36,21 +42,64 @@
 //! }
 //! ```
 
+pub mod derive_support;
+pub mod target_path;
+pub mod trace;
 pub mod utils;
 
-use std::{collections::HashMap, ffi::NulError, path::PathBuf};
+use std::{
+    ffi::NulError,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicIsize, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Mutex, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use implib::{def::ModuleDef, Flavor, ImportLibrary, MachineType};
-use object::read::pe::{PeFile32, PeFile64};
-use utils::ForeignLibrary;
+use forward_dll_pe::{
+    decorate::{import_symbol, ImportSymbol},
+    filter::{ExportAction, ExportFilter},
+    merge::{merge_exports, MergeError, MergeOptions, MergedExport},
+    stub::{
+        export_symbol_name, is_runtime_symbol, ordinal_stub_name, ordinal_symbol_name,
+        DYNAMIC_EXPORTS_FILE,
+    },
+    ExportItem,
+};
+use implib::{
+    def::{ModuleDef, ShortExport},
+    Flavor, ImportLibrary, MachineType,
+};
+use utils::{free_library, get_proc_address_by_module};
 
-pub use forward_dll_derive::ForwardModule;
-use windows_sys::Win32::Foundation::HMODULE;
+pub use forward_dll_derive::{hook, ForwardModule};
+pub use forward_dll_pe::{merge::ConflictPolicy, presets, presets::Preset};
+pub use target_path::TargetPath;
+use windows_sys::Win32::Foundation::{SetLastError, ERROR_PROC_NOT_FOUND, HMODULE};
 
 /// 由过程宏实现的 trait。
 pub trait ForwardModule {
     /// 初始化转发相关的信息，如，加载目标 DLL 获取目标函数地址。
+    ///
+    /// 不调用时，导出函数会在第一次被调用时各自查找目标函数，调用它可以提前发现目标 DLL 或函数不存在的问题。
+    /// 失败后可以再次调用，会清除之前保存的失败结果并重新加载。
     fn init(&self) -> ForwardResult<()>;
+
+    /// 设置所有目标函数无法找到时的处理方式，默认为 `#[forward(on_missing = ...)]` 中指定的值。
+    fn set_failure_policy(&self, failure_policy: FailurePolicy);
+
+    /// 按导出项名称取得目标函数的地址，仅以序号导出的导出项名称为 `#` 加序号。尚未加载目标 DLL 时会先加载。
+    ///
+    /// 没有这个导出项时返回 `Win32Error("GetProcAddress", ERROR_PROC_NOT_FOUND)`。`#[hook]` 通过这个方法取得原函数。
+    fn resolve(&self, name: &str) -> ForwardResult<usize>;
+
+    /// 名称为 `name` 的原函数无法找到时，由 `#[hook]` 生成的代码调用，按照失败策略处理，返回值会作为函数的返回值。
+    #[doc(hidden)]
+    fn handle_failure(&self, name: &'static str) -> isize;
+
+    /// 每个转发的导出函数的调用统计，可以用来查看宿主程序实际用到了哪些导出函数。
+    fn stats(&self) -> Box<dyn Iterator<Item = ExportStats> + '_>;
 }
 
 #[doc(hidden)]
@@ -60,7 +109,11 @@ macro_rules! count {
     ( $x:tt $($xs:tt)* ) => (1usize + $crate::count!($($xs)*));
 }
 
-/// 生成转发的导出函数，以及初始化方法，须在 DllMain 中调用初始化方法，以使生成的函数指向转发的目标函数。
+/// 生成转发的导出函数，以及初始化方法。导出函数第一次被调用时会自动查找目标函数，也可以在 DllMain 中调用初始化方法提前查找。
+///
+/// 导出函数只有 `__forward_dll_macro_<名称>` 这样的内部符号，通过链接器指令以原来的名称导出，
+/// 因此转发 `memcpy` 等与 CRT 或 Rust 自身重名的函数时不会产生符号冲突。内部符号需要用 `global_asm!` 定义，
+/// 所以只能在模块中使用，不能在函数体中使用。
 ///
 /// # Examples
 ///
@@ -90,29 +143,18 @@ macro_rules! count {
 /// #[no_mangle]
 /// pub extern "system" fn DllMain(_inst: isize, reason: u32, _: *const u8) -> u32 {
 ///   if reason == 1 {
-///     // 这里要自行持有底层的 version.dll 的句柄，防止被释放。
-///     let _ = forward_dll::utils::load_library("C:\\Windows\\system32\\version.dll");
 ///     // 调用 forward_all 方法，建立导出函数与目标函数之间的映射关系。
-///     let _ = unsafe { DLL_VERSION_FORWARDER.forward_all() };
+///     let _ = DLL_VERSION_FORWARDER.forward_all();
 ///   }
 ///   1
 /// }
+/// # fn main() {}
 /// ```
 #[macro_export]
 macro_rules! forward_dll {
     ($lib:expr, $name:ident, $($proc:ident)*) => {
-        static mut $name: $crate::DllForwarder<{ $crate::count!($($proc)*) }> = $crate::DllForwarder {
-            initialized: false,
-            module_handle: 0,
-            lib_name: $lib,
-            target_functions_address: [
-                0;
-                $crate::count!($($proc)*)
-            ],
-            target_function_names: [
-                $(stringify!($proc),)*
-            ]
-        };
+        static $name: $crate::DllForwarder<{ $crate::count!($($proc)*) }> =
+            $crate::DllForwarder::new($lib, [$(stringify!($proc),)*]);
         $crate::define_function!($lib, $name, 0, $($proc)*);
     };
 }
@@ -120,89 +162,221 @@ macro_rules! forward_dll {
 #[doc(hidden)]
 #[macro_export]
 macro_rules! define_function {
-    ($lib:expr, $name:ident, $index:expr, ) => {};
-    ($lib:expr, $name:ident, $index:expr, $export_name:ident = $proc:ident $($procs:tt)*) => {
-        const _: () = {
-            fn default_jumper(original_fn_addr: *const ()) -> usize {
-                if original_fn_addr as usize != 0 {
-                    return original_fn_addr as usize;
-                }
-                match $crate::utils::ForeignLibrary::new($lib) {
-                    Ok(lib) => match lib.get_proc_address(std::stringify!($proc)) {
-                        Ok(addr) => return addr as usize,
-                        Err(err) => eprintln!("Error: {}", err)
-                    }
-                    Err(err) => eprintln!("Error: {}", err)
+    // 跳转函数只实现了 x86 与 x86_64，其他架构（如 aarch64）只能使用静态转发。
+    (@unsupported) => {
+        #[cfg(not(any(target_arch = "x86_64", all(windows, target_arch = "x86"))))]
+        compile_error!("Dynamic forwarding only supports x86 and x86_64, use static forwarding instead");
+    };
+    // 生成跳转函数用到的辅助函数。
+    (@body $lib:expr, $name:ident, $index:expr) => {
+            // 由跳转函数调用，返回要跳转到的地址。目标函数无法找到时返回 `fallback_fn`。
+            extern "C" fn jumper() -> usize {
+                $name.record_call($index);
+                match $name.resolve($index) {
+                    Ok(address) => address,
+                    Err(_) => fallback_fn as usize,
                 }
-                exit_fn as usize
             }
 
-            fn exit_fn() {
-                std::process::exit(1);
+            // 目标函数无法找到时跳转到这里，按照失败策略处理。
+            extern "system" fn fallback_fn() -> isize {
+                $name.handle_failure($index)
             }
+    };
+    // 跳转函数的入口，保存参数寄存器后调用 `jumper`，恢复寄存器后跳转到它返回的地址。
+    // `jumper` 中会调用 Win32 API 等任意代码，除了整数参数，x64 上通过 xmm0 ~ xmm3 传递的浮点参数也需要保存，
+    // x86 上保存 `thiscall` 与 `fastcall` 使用的 ecx 与 edx。
+    (@entry $symbol:expr) => {
+        #[cfg(target_arch = "x86_64")]
+        std::arch::global_asm!(
+            concat!(".globl ", $symbol),
+            concat!($symbol, ":"),
+            "push rcx",
+            "push rdx",
+            "push r8",
+            "push r9",
+            "push r10",
+            "push r11",
+            "sub rsp, 0x68",
+            "movdqu [rsp + 0x20], xmm0",
+            "movdqu [rsp + 0x30], xmm1",
+            "movdqu [rsp + 0x40], xmm2",
+            "movdqu [rsp + 0x50], xmm3",
+            "call {jumper}",
+            "movdqu xmm0, [rsp + 0x20]",
+            "movdqu xmm1, [rsp + 0x30]",
+            "movdqu xmm2, [rsp + 0x40]",
+            "movdqu xmm3, [rsp + 0x50]",
+            "add rsp, 0x68",
+            "pop r11",
+            "pop r10",
+            "pop r9",
+            "pop r8",
+            "pop rdx",
+            "pop rcx",
+            "jmp rax",
+            jumper = sym jumper,
+        );
+        // x86 的 Windows 上 C 符号带有 `_` 前缀。
+        #[cfg(all(windows, target_arch = "x86"))]
+        std::arch::global_asm!(
+            concat!(".globl _", $symbol),
+            concat!("_", $symbol, ":"),
+            "push ecx",
+            "push edx",
+            "call {jumper}",
+            "pop edx",
+            "pop ecx",
+            "jmp eax",
+            jumper = sym jumper,
+        );
+    };
+    // 同上，在调用 `jumper` 之前把调用记录交给 `trace::record_frame`。
+    (@entry_trace $symbol:expr) => {
+        #[cfg(target_arch = "x86_64")]
+        std::arch::global_asm!(
+            concat!(".globl ", $symbol),
+            concat!($symbol, ":"),
+            "push rcx",
+            "push rdx",
+            "push r8",
+            "push r9",
+            "push r10",
+            "push r11",
+            "sub rsp, 0x68",
+            "movdqu [rsp + 0x20], xmm0",
+            "movdqu [rsp + 0x30], xmm1",
+            "movdqu [rsp + 0x40], xmm2",
+            "movdqu [rsp + 0x50], xmm3",
+            "lea rcx, [rsp + 0x68]",
+            "call {trace}",
+            "call {jumper}",
+            "movdqu xmm0, [rsp + 0x20]",
+            "movdqu xmm1, [rsp + 0x30]",
+            "movdqu xmm2, [rsp + 0x40]",
+            "movdqu xmm3, [rsp + 0x50]",
+            "add rsp, 0x68",
+            "pop r11",
+            "pop r10",
+            "pop r9",
+            "pop r8",
+            "pop rdx",
+            "pop rcx",
+            "jmp rax",
+            trace = sym trace,
+            jumper = sym jumper,
+        );
+        #[cfg(all(windows, target_arch = "x86"))]
+        std::arch::global_asm!(
+            concat!(".globl _", $symbol),
+            concat!("_", $symbol, ":"),
+            "push ecx",
+            "push edx",
+            "lea eax, [esp + 0xc]",
+            "push eax",
+            "call {trace}",
+            "call {jumper}",
+            "pop edx",
+            "pop ecx",
+            "jmp eax",
+            trace = sym trace,
+            jumper = sym jumper,
+        );
+    };
+    ($lib:expr, $name:ident, $index:expr, ) => {
+        $crate::define_function!(@unsupported);
+    };
+    // `forward_dll!` 生成的跳转函数同样使用内部符号 `__forward_dll_macro_<导出名称>`，
+    // 通过 `.drectve` 节中的链接器指令以 `$export_name` 导出，因此不需要 build.rs。
+    ($lib:expr, $name:ident, $index:expr, $export_name:ident = $proc:ident $($procs:tt)*) => {
+        const _: () = {
+            #[allow(non_snake_case)]
+            mod $export_name {
+                use super::*;
 
-            #[no_mangle]
-            pub extern "system" fn $export_name() -> u32 {
-                #[cfg(target_arch = "x86")]
-                unsafe {
-                    std::arch::asm!(
-                        "push ecx",
-                        "call eax",
-                        "add esp, 4h",
-                        "jmp eax",
-                        in("eax") default_jumper,
-                        in("ecx") $name.target_functions_address[$index],
-                        options(nostack)
-                    );
-                }
-                #[cfg(target_arch = "x86_64")]
-                unsafe {
-                    std::arch::asm!(
-                        "push rcx",
-                        "push rdx",
-                        "push r8",
-                        "push r9",
-                        "push r10",
-                        "push r11",
-                        options(nostack)
-                    );
-                    std::arch::asm!(
-                        "sub rsp, 28h",
-                        "call rax",
-                        "add rsp, 28h",
-                        in("rax") default_jumper,
-                        in("rcx") $name.target_functions_address[$index],
-                        options(nostack)
-                    );
-                    std::arch::asm!(
-                        "pop r11",
-                        "pop r10",
-                        "pop r9",
-                        "pop r8",
-                        "pop rdx",
-                        "pop rcx",
-                        "jmp rax",
-                        options(nostack)
-                    );
-                }
-                1
+                $crate::define_function!(@body $lib, $name, $index);
+                $crate::define_function!(@entry concat!("__forward_dll_macro_", stringify!($export_name)));
+
+                // x86 上 MSVC 的链接器指令使用修饰后的符号名称，MinGW 使用未修饰的名称。
+                #[cfg(all(windows, not(all(target_arch = "x86", target_env = "msvc"))))]
+                std::arch::global_asm!(
+                    ".section .drectve",
+                    concat!(
+                        ".ascii \" -export:",
+                        stringify!($export_name),
+                        "=__forward_dll_macro_",
+                        stringify!($export_name),
+                        "\""
+                    ),
+                    ".text",
+                );
+                #[cfg(all(windows, target_arch = "x86", target_env = "msvc"))]
+                std::arch::global_asm!(
+                    ".section .drectve",
+                    concat!(
+                        ".ascii \" -export:",
+                        stringify!($export_name),
+                        "=___forward_dll_macro_",
+                        stringify!($export_name),
+                        "\""
+                    ),
+                    ".text",
+                );
             }
         };
         $crate::define_function!($lib, $name, ($index + 1), $($procs)*);
     };
+    // 跳转函数使用内部符号 `$symbol`，不以目标中的名称导出，避免与 memcpy 等 CRT 或 Rust 自身的符号冲突。
+    // 需要在 build.rs 中调用 `derive_support::emit_exports` 把它以目标中的名称和序号导出。
+    // 带 #[no_mangle] 或 #[export_name] 的函数总会被 rustc 导出，所以内部符号用 global_asm 定义。
+    ($lib:expr, $name:ident, $index:expr, $fn_name:ident = $symbol:literal $($procs:tt)*) => {
+        #[doc(hidden)]
+        #[allow(non_snake_case)]
+        mod $fn_name {
+            use super::*;
+
+            $crate::define_function!(@body $lib, $name, $index);
+            $crate::define_function!(@entry $symbol);
+        }
+        $crate::define_function!($lib, $name, ($index + 1), $($procs)*);
+    };
     ($lib:expr, $name:ident, $index:expr, $proc:ident $($procs:tt)*) => {
         $crate::define_function!($lib, $name, $index, $proc=$proc $($procs)*);
     };
+    // 同上，跳转函数在入口处把调用记录交给 `trace::record_frame`。
+    (@trace $lib:expr, $name:ident, $index:expr, ) => {
+        $crate::define_function!(@unsupported);
+    };
+    (@trace $lib:expr, $name:ident, $index:expr, $fn_name:ident = $symbol:literal $($procs:tt)*) => {
+        #[doc(hidden)]
+        #[allow(non_snake_case)]
+        mod $fn_name {
+            use super::*;
+
+            $crate::define_function!(@body $lib, $name, $index);
+
+            extern "system" fn trace(frame: *const usize) {
+                unsafe { $crate::trace::record_frame($name.target_function_name($index), frame) };
+            }
+
+            $crate::define_function!(@entry_trace $symbol);
+        }
+        $crate::define_function!(@trace $lib, $name, ($index + 1), $($procs)*);
+    };
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ForwardError {
     /// Win32 API 返回的错误。第一个值为调用的 Win32 API 函数名称，第二个为错误代码。
     Win32Error(&'static str, u32),
     /// 字符串编码错误。
     StringError(NulError),
-    /// 已经初始化过了，不需要再次初始化。
+    /// 已经初始化过了，或者其他线程正在初始化，不需要再次初始化。
     AlreadyInitialized,
+    /// 目标 DLL 的路径无效，如无法识别的占位符、不存在的环境变量。
+    InvalidPath(String),
+    /// 目标 DLL 就是代理 DLL 自己，转发会无限循环。
+    SelfReference,
 }
 
 impl std::fmt::Display for ForwardError {
@@ -213,142 +387,1094 @@ impl std::fmt::Display for ForwardError {
             }
             ForwardError::StringError(ref err) => write!(f, "StringError: {}", err),
             ForwardError::AlreadyInitialized => write!(f, "AlreadyInitialized"),
+            ForwardError::InvalidPath(ref path) => write!(f, "InvalidPath: {}", path),
+            ForwardError::SelfReference => write!(f, "SelfReference"),
         }
     }
 }
 
 impl std::error::Error for ForwardError {}
 
+impl ForwardError {
+    /// 找不到指定的导出项时的错误，与 GetProcAddress 返回的错误相同。
+    #[doc(hidden)]
+    pub fn proc_not_found() -> Self {
+        ForwardError::Win32Error("GetProcAddress", ERROR_PROC_NOT_FOUND)
+    }
+}
+
 pub type ForwardResult<T> = std::result::Result<T, ForwardError>;
 
+/// 目标函数无法找到时，导出函数的处理方式。
+///
+/// `Callback` 与 `ReturnValue` 会让导出函数直接返回，x86 上如果导出函数是带参数的 `stdcall` 函数，
+/// 返回时不会清理参数，调用方的栈会被破坏，这种情况下只能使用 `Exit` 或 `Abort`。
+#[derive(Debug, Clone, Copy)]
+pub enum FailurePolicy {
+    /// 输出错误信息并调用 `std::process::exit(1)` 退出进程，这是默认的处理方式。
+    Exit,
+    /// 输出错误信息并调用 `std::process::abort()` 终止进程。
+    Abort,
+    /// 以导出函数的名称调用回调函数，回调函数的返回值作为导出函数的返回值。
+    Callback(fn(&'static str) -> isize),
+    /// 调用 `SetLastError(ERROR_PROC_NOT_FOUND)` 并返回指定的值。
+    ReturnValue(isize),
+}
+
+impl FailurePolicy {
+    /// 按照处理方式处理 `target` 中的 `name` 无法找到的情况，`err` 为查找时的错误，返回值会作为导出函数的返回值。
+    #[doc(hidden)]
+    pub fn handle(
+        self,
+        name: &'static str,
+        target: TargetPath,
+        err: Option<&ForwardError>,
+    ) -> isize {
+        match self {
+            FailurePolicy::Exit => {
+                report_failure(name, target, err);
+                std::process::exit(1);
+            }
+            FailurePolicy::Abort => {
+                report_failure(name, target, err);
+                std::process::abort();
+            }
+            FailurePolicy::Callback(callback) => callback(name),
+            FailurePolicy::ReturnValue(value) => {
+                unsafe { SetLastError(ERROR_PROC_NOT_FOUND) };
+                value
+            }
+        }
+    }
+}
+
+/// 输出 `target` 中的 `name` 查找失败的原因。
+fn report_failure(name: &str, target: TargetPath, err: Option<&ForwardError>) {
+    match err {
+        Some(ForwardError::Win32Error("GetProcAddress", ERROR_PROC_NOT_FOUND)) | None => {
+            eprintln!("Error: {} is not found in {}", name, target)
+        }
+        Some(err) => eprintln!("Error: Failed to forward {} to {}: {}", name, target, err),
+    }
+}
+
+/// 一个导出函数的调用统计。只统计经过跳转函数的调用，`#[hook]` 替换的函数不计入。
+#[derive(Debug, Clone, Copy)]
+pub struct ExportStats {
+    /// 导出项在目标 DLL 中的名称，仅以序号导出时为 `#` 加序号。
+    pub name: &'static str,
+    /// 代理 DLL 导出这个函数时使用的序号，未知时为 `None`。
+    pub ordinal: Option<u32>,
+    /// 调用次数。
+    pub count: u64,
+    /// 第一次调用的时间。
+    pub first_call: Option<SystemTime>,
+    /// 最近一次调用的时间。
+    pub last_call: Option<SystemTime>,
+}
+
 /// DLL 转发类型的具体实现。该类型不要自己实例化，应调用 forward_dll 宏生成具体的实例。
+///
+/// 所有状态都是原子变量或锁，可以放在普通的 `static` 中，初始化与导出函数的调用可以发生在任意线程。
 pub struct DllForwarder<const N: usize> {
-    pub initialized: bool,
-    pub module_handle: HMODULE,
-    pub target_functions_address: [usize; N],
-    pub target_function_names: [&'static str; N],
-    pub lib_name: &'static str,
+    /// 初始化的状态，见 `UNINITIALIZED`、`INITIALIZING` 与 `INITIALIZED`。
+    state: AtomicU8,
+    module_handle: AtomicIsize,
+    /// 目标 `DLL` 加载失败时的错误，`forward_all` 清除之前不再尝试加载。
+    load_error: Mutex<Option<ForwardError>>,
+    target_functions_address: [AtomicUsize; N],
+    /// 每个导出函数查找失败时的错误，`forward_all` 清除之前不再查找。
+    failures: [Mutex<Option<ForwardError>>; N],
+    /// 调用次数，以及第一次与最近一次调用的时间（自 UNIX_EPOCH 起的微秒数，0 表示没有调用过）。
+    call_counts: [AtomicU64; N],
+    first_calls: [AtomicU64; N],
+    last_calls: [AtomicU64; N],
+    /// 导出时使用的序号，0 表示未知。
+    ordinals: [u32; N],
+    target_function_names: [&'static str; N],
+    target: TargetPath,
+    failure_policy: RwLock<FailurePolicy>,
 }
 
+/// 取得锁，其他线程持有锁时 panic 也不影响保存的值。
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+const UNINITIALIZED: u8 = 0;
+const INITIALIZING: u8 = 1;
+const INITIALIZED: u8 = 2;
+
 impl<const N: usize> DllForwarder<N> {
+    /// 创建一个尚未初始化的实例，`target_function_names` 中以 `#` 开头的名称（如 `#3`）按序号查找。
+    pub const fn new(lib_name: &'static str, target_function_names: [&'static str; N]) -> Self {
+        Self::with_failure_policy(lib_name, target_function_names, FailurePolicy::Exit)
+    }
+
+    /// 同 `new`，同时指定目标函数无法找到时的处理方式。
+    pub const fn with_failure_policy(
+        lib_name: &'static str,
+        target_function_names: [&'static str; N],
+        failure_policy: FailurePolicy,
+    ) -> Self {
+        Self::with_target(
+            TargetPath::new(lib_name),
+            target_function_names,
+            failure_policy,
+        )
+    }
+
+    /// 同 `with_failure_policy`，目标 DLL 的路径在运行时解析，可以包含占位符或多个候选路径，见 `TargetPath`。
+    pub const fn with_target(
+        target: TargetPath,
+        target_function_names: [&'static str; N],
+        failure_policy: FailurePolicy,
+    ) -> Self {
+        // 只用于初始化数组，每个元素都是新的原子变量。
+        #[allow(clippy::declare_interior_mutable_const)]
+        const UNRESOLVED: AtomicUsize = AtomicUsize::new(0);
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU64 = AtomicU64::new(0);
+        #[allow(clippy::declare_interior_mutable_const)]
+        const NO_FAILURE: Mutex<Option<ForwardError>> = Mutex::new(None);
+        Self {
+            state: AtomicU8::new(UNINITIALIZED),
+            module_handle: AtomicIsize::new(0),
+            load_error: Mutex::new(None),
+            target_functions_address: [UNRESOLVED; N],
+            failures: [NO_FAILURE; N],
+            call_counts: [ZERO; N],
+            first_calls: [ZERO; N],
+            last_calls: [ZERO; N],
+            ordinals: [0; N],
+            target_function_names,
+            target,
+            failure_policy: RwLock::new(failure_policy),
+        }
+    }
+
+    /// 设置每个导出函数导出时使用的序号，只用于调用统计。
+    pub const fn with_ordinals(mut self, ordinals: [u32; N]) -> Self {
+        self.ordinals = ordinals;
+        self
+    }
+
+    /// 目标函数无法找到时的处理方式。
+    pub fn failure_policy(&self) -> FailurePolicy {
+        *self
+            .failure_policy
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// 设置目标函数无法找到时的处理方式。
+    pub fn set_failure_policy(&self, failure_policy: FailurePolicy) {
+        *self
+            .failure_policy
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = failure_policy;
+    }
+
+    /// 第 `index` 个导出函数的目标函数无法找到时，由导出函数调用，按照失败策略处理，返回值会作为导出函数的返回值。
+    #[doc(hidden)]
+    pub fn handle_failure(&self, index: usize) -> isize {
+        self.failure_policy().handle(
+            self.target_function_names[index],
+            self.target,
+            self.failure(index).as_ref(),
+        )
+    }
+
     /// 将所有函数的跳转地址设置为对应的 DLL 的同名函数地址。
-    pub fn forward_all(&mut self) -> ForwardResult<()> {
-        if self.initialized {
+    ///
+    /// 只能成功调用一次，已经初始化或者其他线程正在初始化时返回 `AlreadyInitialized`。初始化失败后可以再次调用，
+    /// 每次调用都会先清除之前保存的加载或查找失败的结果，重新加载目标 `DLL` 并查找。
+    pub fn forward_all(&self) -> ForwardResult<()> {
+        if self
+            .state
+            .compare_exchange(
+                UNINITIALIZED,
+                INITIALIZING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
             return Err(ForwardError::AlreadyInitialized);
         }
 
-        let lib = ForeignLibrary::new(self.lib_name)?;
-        for index in 0..self.target_functions_address.len() {
-            let addr_in_remote_module = lib.get_proc_address(self.target_function_names[index])?;
-            self.target_functions_address[index] = addr_in_remote_module as *const usize as usize;
+        // 之前的失败可能是暂时的，如环境变量或 DLL 搜索目录还没有设置好。
+        *lock(&self.load_error) = None;
+        for failure in &self.failures {
+            *lock(failure) = None;
         }
+        let result = self.resolve_all();
+        let state = if result.is_ok() {
+            INITIALIZED
+        } else {
+            UNINITIALIZED
+        };
+        self.state.store(state, Ordering::Release);
+        result
+    }
 
-        self.module_handle = lib.into_raw();
-        self.initialized = true;
-
+    fn resolve_all(&self) -> ForwardResult<()> {
+        for index in 0..N {
+            self.resolve(index)?;
+        }
         Ok(())
     }
-}
 
-struct ExportItem {
-    ordinal: u32,
-    name: Option<String>,
+    /// 取得第 `index` 个导出函数的跳转地址，尚未取得时加载目标 `DLL` 并查找，结果会保存下来，之后直接返回。
+    /// 失败的结果同样会被保存下来，再次调用 `forward_all` 之前直接返回同样的错误，不会每次调用都重新加载目标 `DLL`。
+    ///
+    /// 导出函数在 `forward_all` 之前被调用时也会通过这个方法取得地址，因此 `forward_all` 不是必须调用的。
+    pub fn resolve(&self, index: usize) -> ForwardResult<usize> {
+        let address = self.target_address(index);
+        if address != 0 {
+            return Ok(address);
+        }
+        if let Some(err) = self.failure(index) {
+            return Err(err);
+        }
+        let address = self
+            .module()
+            .and_then(|module| {
+                get_proc_address_by_module(module, self.target_function_names[index])
+            })
+            .inspect_err(|err| *lock(&self.failures[index]) = Some(err.clone()))?
+            as usize;
+        // 多个线程同时查找时得到的地址相同，直接覆盖即可。
+        self.target_functions_address[index].store(address, Ordering::Release);
+        Ok(address)
+    }
+
+    /// 第 `index` 个导出函数查找失败时的错误，尚未查找或查找成功时为 `None`。
+    pub fn failure(&self, index: usize) -> Option<ForwardError> {
+        lock(&self.failures[index]).clone()
+    }
+
+    /// 取得目标 `DLL` 的模块句柄，第一次调用时加载。模块在进程的整个生命周期内都不会被释放，保证取得的地址一直有效。
+    fn module(&self) -> ForwardResult<HMODULE> {
+        let module_handle = self.module_handle.load(Ordering::Acquire);
+        if module_handle != 0 {
+            return Ok(module_handle);
+        }
+        if let Some(err) = lock(&self.load_error).clone() {
+            return Err(err);
+        }
+        let module_handle = self
+            .target
+            .load()
+            .inspect_err(|err| *lock(&self.load_error) = Some(err.clone()))?;
+        match self.module_handle.compare_exchange(
+            0,
+            module_handle,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => Ok(module_handle),
+            Err(existing) => {
+                // 其他线程已经加载过了，释放这里多增加的引用计数。
+                free_library(module_handle);
+                Ok(existing)
+            }
+        }
+    }
+
+    /// 是否已经初始化。
+    pub fn is_initialized(&self) -> bool {
+        self.state.load(Ordering::Acquire) == INITIALIZED
+    }
+
+    /// 目标 `DLL` 的模块句柄，尚未加载时为 0。
+    pub fn module_handle(&self) -> HMODULE {
+        self.module_handle.load(Ordering::Acquire)
+    }
+
+    /// 目标 `DLL` 的路径，有多个候选路径时为第一个，未展开占位符。
+    pub fn lib_name(&self) -> &'static str {
+        self.target.paths()[0]
+    }
+
+    /// 目标 `DLL` 的路径。
+    pub fn target(&self) -> TargetPath {
+        self.target
+    }
+
+    /// 由跳转函数调用，记录第 `index` 个导出函数被调用了一次。
+    ///
+    /// 会读取系统时间，可能用到浮点寄存器，跳转函数在调用前保存了所有参数寄存器。
+    #[doc(hidden)]
+    pub fn record_call(&self, index: usize) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_micros() as u64)
+            .unwrap_or(0)
+            .max(1);
+        self.call_counts[index].fetch_add(1, Ordering::Relaxed);
+        let _ =
+            self.first_calls[index].compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed);
+        self.last_calls[index].store(now, Ordering::Relaxed);
+    }
+
+    /// 第 `index` 个导出函数的调用统计。
+    pub fn export_stats(&self, index: usize) -> ExportStats {
+        let timestamp =
+            |micros: u64| (micros != 0).then(|| UNIX_EPOCH + Duration::from_micros(micros));
+        ExportStats {
+            name: self.target_function_names[index],
+            ordinal: (self.ordinals[index] != 0).then_some(self.ordinals[index]),
+            count: self.call_counts[index].load(Ordering::Relaxed),
+            first_call: timestamp(self.first_calls[index].load(Ordering::Relaxed)),
+            last_call: timestamp(self.last_calls[index].load(Ordering::Relaxed)),
+        }
+    }
+
+    /// 所有导出函数的调用统计。
+    pub fn stats(&self) -> impl Iterator<Item = ExportStats> + '_ {
+        (0..N).map(|index| self.export_stats(index))
+    }
+
+    /// 名称为 `name` 的导出函数的序号，没有时为 `None`。
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.target_function_names
+            .iter()
+            .position(|target_name| *target_name == name)
+    }
+
+    /// 第 `index` 个导出函数在目标 `DLL` 中的名称。
+    pub fn target_function_name(&self, index: usize) -> &'static str {
+        self.target_function_names[index]
+    }
+
+    /// 第 `index` 个导出函数的跳转地址，尚未取得时为 0。
+    pub fn target_address(&self, index: usize) -> usize {
+        self.target_functions_address[index].load(Ordering::Acquire)
+    }
 }
 
 /// 转发目标 `DLL` 的所有函数，同时会确保 `ordinal` 与目标函数一致。这个函数会读取目标 `DLL` 以获得导出函数信息，因此，要确保目标 `DLL` 在编译期存在。
 pub fn forward_dll(dll_path: &str) -> Result<(), String> {
-    forward_dll_with_dev_path(dll_path, dll_path)
+    ForwardConfig::new().target(dll_path).emit()
 }
 
 /// 转发目标 `DLL` 的所有函数。与 `forward_dll` 类似，区别在于这个函数可以指定在编译时的目标 `DLL` 路径。
 pub fn forward_dll_with_dev_path(dll_path: &str, dev_dll_path: &str) -> Result<(), String> {
-    let exports = get_dll_export_names(dev_dll_path)?;
-    forward_dll_impl(dll_path, exports.as_slice())
+    ForwardConfig::new()
+        .target(dll_path)
+        .dev_path(dev_dll_path)
+        .emit()
+}
+
+/// 转发目标 `DLL` 的所有函数。与 `forward_dll` 类似，区别在于目标 `DLL` 中本身就是转发的导出项（如 `NTDLL.RtlXxx`、`api-ms-win-*`），会直接转发到最终目标，而不是经过目标 `DLL` 中转。
+///
+/// 这些导出项会被记录到 `OUT_DIR` 下的 `<dll 名称>_proxy_forwarders.txt` 中，并在构建时输出一条警告。
+pub fn forward_dll_follow_forwarders(dll_path: &str) -> Result<(), String> {
+    ForwardConfig::new()
+        .target(dll_path)
+        .follow_forwarders(true)
+        .emit()
+}
+
+/// 转发系统目录中的常见 `DLL`，如 `forward_dll_preset(Preset::Version)`。导出表是内置的，不要求在编译期存在 dll，
+/// 运行时转发到 `C:\Windows\System32` 下的同名 `DLL`。
+///
+/// 静态转发的目标路径写在代理 DLL 的导出表中，不能使用 `{system}` 这样的占位符。Windows 不在 `C:` 盘时，
+/// 请使用 `ForwardConfig::new().target(路径).preset(preset)` 指定目标路径。
+pub fn forward_dll_preset(preset: Preset) -> Result<(), String> {
+    ForwardConfig::new()
+        .target(&format!("C:\\Windows\\System32\\{}", preset.dll_name()))
+        .preset(preset)
+        .emit()
 }
 
 /// 转发目标 `DLL` 的所有函数。与 `forward_dll` 类似，区别在于这个函数不要求在编译期存在 dll。
 pub fn forward_dll_with_exports(dll_path: &str, exports: &[(u32, &str)]) -> Result<(), String> {
-    forward_dll_impl(
-        dll_path,
-        exports
-            .iter()
-            .map(|(ord, name)| ExportItem {
-                ordinal: *ord,
-                name: Some(name.to_string()),
-            })
-            .collect::<Vec<_>>()
-            .as_slice(),
-    )
+    ForwardConfig::new()
+        .target(dll_path)
+        .exports(exports)
+        .emit()
 }
 
-fn forward_dll_impl(dll_path: &str, exports: &[ExportItem]) -> Result<(), String> {
-    const SUFFIX: &str = ".dll";
-    let dll_path_without_ext = if dll_path.to_ascii_lowercase().ends_with(SUFFIX) {
-        &dll_path[..dll_path.len() - SUFFIX.len()]
-    } else {
-        dll_path
-    };
+/// 构造代理 DLL 的配置，所有选项设置完成后调用 `emit` 输出链接参数并生成 Import Library。
+///
+/// 可以添加多个目标，把多个 `DLL` 的导出项合并到同一个代理 DLL 中，最终只输出一组链接参数和一个 Import Library。
+/// `dev_path` 与 `exports` 作用于最近一次添加的目标。
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use forward_dll::ForwardConfig;
+///
+/// ForwardConfig::new()
+///     .target("C:\\Windows\\system32\\version.dll")
+///     // 编译时从另一个路径读取导出表。
+///     .dev_path("deps/version.dll")
+///     // 只导出 GetFileVersionInfo* 与 VerQueryValue*。
+///     .include("GetFileVersionInfo*")
+///     .include("VerQueryValue*")
+///     // GetFileVersionInfoW 不转发，由代理 DLL 自己实现，序号保持不变。
+///     .exclude("GetFileVersionInfoW")
+///     // 把 VerQueryValueW 以 VerQueryValueW2 的名称导出。
+///     .rename("VerQueryValueW", "VerQueryValueW2")
+///     .emit()
+///     .unwrap();
+/// ```
+///
+/// 合并多个目标：
+///
+/// ```rust,no_run
+/// use forward_dll::{ConflictPolicy, ForwardConfig};
+///
+/// ForwardConfig::new()
+///     .target("C:\\Program Files\\App\\core.dll")
+///     .target("C:\\Program Files\\App\\core_ext.dll")
+///     // 两个 DLL 都导出了同名函数时，默认使用先添加的。
+///     .on_conflict(ConflictPolicy::FirstWins)
+///     // 单独指定 Init 使用 core_ext.dll 中的实现。
+///     .prefer("Init", "C:\\Program Files\\App\\core_ext.dll")
+///     // 序号冲突时为后出现的导出项重新分配序号。
+///     .remap_ordinals(true)
+///     .emit()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ForwardConfig {
+    targets: Vec<ForwardTarget>,
+    on_conflict: ConflictPolicy,
+    prefer: Vec<(String, String)>,
+    remap_ordinals: bool,
+    follow_forwarders: bool,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    rename: Vec<(String, String)>,
+    output_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+struct ForwardTarget {
+    dll_path: String,
+    dev_path: Option<String>,
+    exports: Option<Vec<(u32, String)>>,
+    preset: Option<Preset>,
+}
+
+impl ForwardConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 添加一个转发目标，`dll_path` 为运行时转发到的路径。
+    pub fn target(mut self, dll_path: &str) -> Self {
+        self.targets.push(ForwardTarget {
+            dll_path: dll_path.to_string(),
+            dev_path: None,
+            exports: None,
+            preset: None,
+        });
+        self
+    }
+
+    /// 指定最近添加的目标在编译时读取导出表的路径，默认与运行时的路径相同。
+    pub fn dev_path(mut self, dev_dll_path: &str) -> Self {
+        self.last_target("dev_path").dev_path = Some(dev_dll_path.to_string());
+        self
+    }
+
+    /// 直接指定最近添加的目标的导出项（序号与名称），这样编译时不需要读取目标 `DLL`。
+    pub fn exports(mut self, exports: &[(u32, &str)]) -> Self {
+        self.last_target("exports").exports = Some(
+            exports
+                .iter()
+                .map(|(ordinal, name)| (*ordinal, name.to_string()))
+                .collect(),
+        );
+        self
+    }
+
+    /// 使用内置的导出表作为最近添加的目标的导出项，编译时不需要读取目标 `DLL`，见 `presets`。
+    pub fn preset(mut self, preset: Preset) -> Self {
+        self.last_target("preset").preset = Some(preset);
+        self
+    }
+
+    /// 多个目标中存在同名导出项时的处理方式，默认报错。
+    pub fn on_conflict(mut self, policy: ConflictPolicy) -> Self {
+        self.on_conflict = policy;
+        self
+    }
 
-    let out_dir = get_tmp_dir();
+    /// 指定名为 `export_name` 的导出项使用 `dll_path` 中的实现，优先于 `on_conflict`。
+    pub fn prefer(mut self, export_name: &str, dll_path: &str) -> Self {
+        self.prefer
+            .push((export_name.to_string(), dll_path.to_string()));
+        self
+    }
+
+    /// 多个目标中的导出项序号冲突时，是否为后出现的导出项重新分配序号，默认报错。
+    pub fn remap_ordinals(mut self, remap: bool) -> Self {
+        self.remap_ordinals = remap;
+        self
+    }
+
+    /// 目标 `DLL` 中本身就是转发的导出项，是否直接转发到最终目标，默认经过目标 `DLL` 中转。
+    ///
+    /// 这些导出项会被记录到输出目录下的 `<dll 名称>_proxy_forwarders.txt` 中，并在构建时输出一条警告。
+    pub fn follow_forwarders(mut self, follow: bool) -> Self {
+        self.follow_forwarders = follow;
+        self
+    }
 
-    // 有些导出符号没有名称，在编译的过程中，临时取一个符号名。
-    let mut anonymous_map = HashMap::new();
-    let mut anonymous_name_id = 0;
+    /// 只导出名称匹配 `pattern` 的导出项，支持 `*` 与 `?` 通配符，可以调用多次。没有调用时导出所有导出项。
+    pub fn include(mut self, pattern: &str) -> Self {
+        self.include.push(pattern.to_string());
+        self
+    }
+
+    /// 不转发名称匹配 `pattern` 的导出项，支持 `*` 与 `?` 通配符，可以调用多次。
+    ///
+    /// 这些导出项仍然以原来的序号导出，但绑定到代理 DLL 中自己实现的同名函数（`#[no_mangle]`），
+    /// 按序号导入的程序同样会调用到自己的实现。
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(pattern.to_string());
+        self
+    }
+
+    /// 把目标中名为 `export_name` 的导出项以 `new_name` 的名称导出。
+    pub fn rename(mut self, export_name: &str, new_name: &str) -> Self {
+        self.rename
+            .push((export_name.to_string(), new_name.to_string()));
+        self
+    }
+
+    /// 生成的 Import Library 等文件的存放目录，默认为 `OUT_DIR`。
+    pub fn output_dir<P: Into<PathBuf>>(mut self, output_dir: P) -> Self {
+        self.output_dir = Some(output_dir.into());
+        self
+    }
+
+    /// 读取所有目标的导出表，合并后输出链接参数并生成 Import Library。
+    pub fn emit(self) -> Result<(), String> {
+        let (exports, filter) = self.merge()?;
+        self.emit_exports(&exports, &filter)
+    }
 
-    // 输出链接参数，转发入口点到目标库。
-    for ExportItem { name, ordinal } in exports {
-        match name {
-            Some(name) => println!(
-                "cargo:rustc-link-arg=/EXPORT:{name}={dll_path_without_ext}.{name},@{ordinal}"
+    /// 为 `#[derive(ForwardModule)]` 生成的跳转函数输出链接参数，目标等参数应与 `#[forward(...)]` 中的一致。
+    ///
+    /// 过程宏生成的跳转函数只有内部符号名称，需要通过这里输出的链接参数以目标中的名称和序号导出，
+    /// 这样 `memcpy` 等与 CRT 或 Rust 自身重名的导出项不会产生符号冲突，这些导出项会在构建时输出一条警告。
+    /// `exclude` 的导出项绑定到自己实现的同名函数，数据导出会被忽略，不会生成 Import Library。
+    /// 过程宏总是为冲突的序号重新分配序号，有多个目标时需要同时设置 `remap_ordinals(true)`。
+    pub fn emit_dynamic(self) -> Result<(), String> {
+        let (exports, filter) = self.merge()?;
+
+        let mut entries = Vec::new();
+        let mut collisions = Vec::new();
+        for MergedExport { item, .. } in &exports {
+            let action = filter.action(item);
+            // 过程宏不会为数据导出生成跳转函数。
+            if action == ExportAction::Skip || (action == ExportAction::Forward && item.is_data()) {
+                continue;
+            }
+            let (name, symbol) = match item.name_lossy() {
+                Some(name) => {
+                    let symbol = export_symbol_name(&name);
+                    (self.public_name(name), symbol)
+                }
+                None => (
+                    ordinal_stub_name(item.ordinal),
+                    ordinal_symbol_name(item.ordinal),
+                ),
+            };
+            let target = match action {
+                ExportAction::Local => None,
+                _ => Some(symbol),
+            };
+            if target.is_some() && is_runtime_symbol(&name) {
+                collisions.push(name.clone());
+            }
+            if entries
+                .iter()
+                .any(|entry: &ForwardEntry| entry.name == name)
+            {
+                return Err(format!("Export {name} is defined more than once"));
+            }
+            entries.push(ForwardEntry {
+                name,
+                target,
+                ordinal: item.ordinal,
+                no_name: item.name.is_none(),
+                data: item.is_data(),
+            });
+        }
+
+        if !collisions.is_empty() {
+            println!(
+                "cargo:warning=These exports have the same names as CRT or Rust symbols and are only exported through the export table: {}",
+                collisions.join(", ")
+            );
+        }
+
+        record_dynamic_exports(&entries)?;
+
+        let out_dir = self.output_dir.clone().unwrap_or_else(get_tmp_dir);
+        let proxy_name = unique_proxy_name(&dll_file_stem(&self.targets[0].dll_path));
+        emit_link_args(&entries, &out_dir.join(format!("{proxy_name}.def")))
+    }
+
+    /// 读取所有目标的导出表并合并，返回合并后的导出项和筛选规则。
+    fn merge(&self) -> Result<(Vec<MergedExport>, ExportFilter), String> {
+        if self.targets.is_empty() {
+            return Err("No target dll".to_string());
+        }
+
+        let mut prefer = Vec::new();
+        for (export_name, dll_path) in &self.prefer {
+            let source = self
+                .targets
+                .iter()
+                .position(|target| &target.dll_path == dll_path)
+                .ok_or_else(|| format!("{dll_path} is not a target"))?;
+            prefer.push((export_name.as_bytes().to_vec(), source));
+        }
+        let options = MergeOptions {
+            on_conflict: self.on_conflict,
+            prefer,
+            remap_ordinals: self.remap_ordinals,
+        };
+
+        let sets = self
+            .targets
+            .iter()
+            .map(ForwardTarget::read_exports)
+            .collect::<Result<Vec<_>, _>>()?;
+        let exports = merge_exports(&sets, &options).map_err(|err| match err {
+            MergeError::NameConflict {
+                name,
+                first,
+                second,
+            } => format!(
+                "Export {name} exists in both {} and {}, use on_conflict or prefer to choose one",
+                self.targets[first].dll_path, self.targets[second].dll_path
             ),
-            None => {
-                anonymous_name_id += 1;
-                let fn_name = format!("forward_dll_anonymous_{anonymous_name_id}");
+            MergeError::OrdinalConflict {
+                ordinal,
+                first,
+                second,
+            } => format!(
+                "Ordinal {ordinal} is used by both {} and {}, use remap_ordinals to renumber",
+                self.targets[first].dll_path, self.targets[second].dll_path
+            ),
+        })?;
+        for entry in &exports {
+            if let Some(ordinal) = entry.remapped_from {
                 println!(
-                    "cargo:rustc-link-arg=/EXPORT:{fn_name}={dll_path_without_ext}.#{ordinal},@{ordinal},NONAME"
+                    "cargo:warning=Ordinal of {} in {} is remapped from {ordinal} to {}",
+                    entry
+                        .item
+                        .name_lossy()
+                        .unwrap_or_else(|| format!("#{ordinal}")),
+                    self.targets[entry.source].dll_path,
+                    entry.item.ordinal
                 );
-                anonymous_map.insert(ordinal, fn_name);
             }
+        }
+
+        let filter = ExportFilter {
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
         };
+        let all_exports: Vec<_> = exports.iter().map(|entry| entry.item.clone()).collect();
+        for pattern in filter.unused_patterns(&all_exports) {
+            println!("cargo:warning=Pattern {pattern} does not match any export");
+        }
+
+        Ok((exports, filter))
     }
 
-    // 构造 Import Library。
-    let exports_def = String::from("LIBRARY version\nEXPORTS\n")
-        + exports
+    /// 取得导出项在代理 DLL 中导出的名称，即 `rename` 之后的名称。
+    fn public_name(&self, name: String) -> String {
+        self.rename
             .iter()
-            .map(|ExportItem { name, ordinal }| match name {
-                Some(name) => format!("  {name} @{ordinal}\n"),
-                None => {
-                    let fn_name = anonymous_map.get(ordinal).unwrap();
-                    format!("  {fn_name} @{ordinal} NONAME\n")
+            .find(|(export_name, _)| *export_name == name)
+            .map(|(_, new_name)| new_name.clone())
+            .unwrap_or(name)
+    }
+
+    fn last_target(&mut self, option: &str) -> &mut ForwardTarget {
+        self.targets
+            .last_mut()
+            .unwrap_or_else(|| panic!("ForwardConfig::{option} must be called after target"))
+    }
+
+    /// 把合并后的导出项转换为转发的导出项，同时返回直接转发到最终目标的导出项，用于输出报告。
+    fn forward_entries(
+        &self,
+        exports: &[MergedExport],
+        filter: &ExportFilter,
+    ) -> Result<(Vec<ForwardEntry>, Vec<String>), String> {
+        const SUFFIX: &str = ".dll";
+        let paths_without_ext: Vec<_> = self
+            .targets
+            .iter()
+            .map(|target| {
+                let dll_path = target.dll_path.as_str();
+                if dll_path.to_ascii_lowercase().ends_with(SUFFIX) {
+                    &dll_path[..dll_path.len() - SUFFIX.len()]
+                } else {
+                    dll_path
                 }
             })
-            .collect::<String>()
-            .as_str();
-    #[cfg(target_arch = "x86_64")]
-    let machine = MachineType::AMD64;
-    #[cfg(target_arch = "x86")]
-    let machine = MachineType::I386;
-    let mut def = ModuleDef::parse(&exports_def, machine)
-        .map_err(|err| format!("ImportLibrary::new error: {err}"))?;
-    for item in def.exports.iter_mut() {
-        item.symbol_name = item.name.trim_start_matches('_').to_string();
-    }
-    let lib = ImportLibrary::from_def(def, machine, Flavor::Msvc);
-    let version_lib_path = out_dir.join("version_proxy.lib");
-    let mut lib_file = std::fs::OpenOptions::new()
+            .collect();
+
+        // 有些导出符号没有名称，在编译的过程中，临时取一个符号名。
+        let mut anonymous_name_id = 0;
+
+        // 目标 DLL 中本身就是转发的导出项，直接转发到最终目标时记录下来，用于输出报告。
+        let mut chained = Vec::new();
+
+        let mut entries = Vec::new();
+        for MergedExport {
+            source,
+            item,
+            remapped_from,
+        } in exports
+        {
+            let action = filter.action(item);
+            if action == ExportAction::Skip {
+                continue;
+            }
+            let dll_path_without_ext = paths_without_ext[*source];
+            let ordinal = item.ordinal;
+            // 序号被重新分配时，仍然要按目标 DLL 中原来的序号转发。
+            let target_ordinal = remapped_from.unwrap_or(ordinal);
+            let forwarder = item
+                .forwarder
+                .as_deref()
+                .filter(|_| self.follow_forwarders)
+                .map(String::from_utf8_lossy);
+            let (name, target) = match item.name.as_deref().map(String::from_utf8_lossy) {
+                Some(name) => {
+                    let target = match forwarder {
+                        // 不转发的导出项绑定到代理 DLL 中自己实现的同名函数。
+                        _ if action == ExportAction::Local => None,
+                        Some(forwarder) => {
+                            chained.push(format!("{name} -> {forwarder}"));
+                            Some(forwarder.into_owned())
+                        }
+                        None => Some(format!("{dll_path_without_ext}.{name}")),
+                    };
+                    (self.public_name(name.into_owned()), target)
+                }
+                None => {
+                    anonymous_name_id += 1;
+                    let fn_name = format!("forward_dll_anonymous_{anonymous_name_id}");
+                    let target = match forwarder {
+                        Some(forwarder) => {
+                            chained.push(format!("#{ordinal} -> {forwarder}"));
+                            Some(forwarder.into_owned())
+                        }
+                        None => Some(format!("{dll_path_without_ext}.#{target_ordinal}")),
+                    };
+                    (fn_name, target)
+                }
+            };
+            if entries
+                .iter()
+                .any(|entry: &ForwardEntry| entry.name == name)
+            {
+                return Err(format!("Export {name} is defined more than once"));
+            }
+            entries.push(ForwardEntry {
+                name,
+                target,
+                ordinal,
+                no_name: item.name.is_none(),
+                // 数据导出需要带上 DATA，否则导入方会把它当作函数来链接。
+                data: item.is_data(),
+            });
+        }
+        Ok((entries, chained))
+    }
+
+    /// 输出链接参数并生成 Import Library，`exports` 中的 `source` 对应 `targets` 的下标。
+    /// Import Library 以第一个目标命名。
+    fn emit_exports(&self, exports: &[MergedExport], filter: &ExportFilter) -> Result<(), String> {
+        let out_dir = self.output_dir.clone().unwrap_or_else(get_tmp_dir);
+        let library_name = dll_file_stem(&self.targets[0].dll_path);
+        let proxy_name = unique_proxy_name(&library_name);
+
+        let (entries, chained) = self.forward_entries(exports, filter)?;
+        if !chained.is_empty() {
+            let report_path = out_dir.join(format!("{proxy_name}_forwarders.txt"));
+            std::fs::write(&report_path, chained.join("\n"))
+                .map_err(|err| format!("Failed to write forwarder report: {err}"))?;
+            println!(
+                "cargo:warning={} exports are forwarded directly to their final targets, see {}",
+                chained.len(),
+                report_path.display()
+            );
+        }
+
+        let def_path = out_dir.join(format!("{proxy_name}.def"));
+        emit_link_args(&entries, &def_path)?;
+
+        let flavor = target_flavor();
+        let lib = import_library(library_name, &entries, target_machine()?, flavor)?;
+        // MinGW 的 ld 按 lib{name}.a 查找库文件。
+        let lib_path = match flavor {
+            Flavor::Msvc => out_dir.join(format!("{proxy_name}.lib")),
+            Flavor::Gnu => out_dir.join(format!("lib{proxy_name}.a")),
+        };
+        let mut lib_file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(lib_path)
+            .map_err(|err| format!("OpenOptions::open error: {err}"))?;
+        lib.write_to(&mut lib_file)
+            .map_err(|err| format!("ImportLibrary::write_to error: {err}"))?;
+
+        println!("cargo:rustc-link-search={}", out_dir.display());
+        println!("cargo:rustc-link-lib={proxy_name}");
+
+        Ok(())
+    }
+}
+
+impl ForwardTarget {
+    /// 读取目标的导出项，指定了 `exports` 或 `preset` 时不读取 `DLL`。
+    fn read_exports(&self) -> Result<Vec<ExportItem>, String> {
+        if let (None, Some(preset)) = (&self.exports, self.preset) {
+            let machine = target_machine()?;
+            return preset.exports(machine as u16).ok_or_else(|| {
+                format!(
+                    "The preset {} is not available for the target arch {}",
+                    preset.name(),
+                    target_arch()
+                )
+            });
+        }
+        match &self.exports {
+            Some(exports) => Ok(exports
+                .iter()
+                .map(|(ordinal, name)| ExportItem::code(*ordinal, Some(name)))
+                .collect()),
+            None => read_target_exports(self.dev_path.as_deref().unwrap_or(&self.dll_path)),
+        }
+    }
+}
+
+/// 一条转发的导出项，`target` 为转发目标，如 `C:\Windows\system32\version.GetFileVersionInfoA`，
+/// 为 `None` 时绑定到代理 DLL 中自己实现的同名函数。
+struct ForwardEntry {
+    name: String,
+    target: Option<String>,
+    ordinal: u32,
+    no_name: bool,
+    data: bool,
+}
+
+/// 输出链接参数，转发入口点到目标库。`target` 为 `None` 的导出项只设置序号。
+///
+/// MinGW 的 ld 不认识 /EXPORT，导出项会写进 `def_path` 这个 .def 文件，作为链接器的输入文件。
+fn emit_link_args(entries: &[ForwardEntry], def_path: &Path) -> Result<(), String> {
+    match target_flavor() {
+        Flavor::Msvc => {
+            for entry in entries {
+                let ForwardEntry {
+                    name,
+                    target,
+                    ordinal,
+                    ..
+                } = entry;
+                let target = target
+                    .as_ref()
+                    .map(|target| format!("={target}"))
+                    .unwrap_or_default();
+                let no_name = if entry.no_name { ",NONAME" } else { "" };
+                let data = if entry.data { ",DATA" } else { "" };
+                println!("cargo:rustc-link-arg=/EXPORT:{name}{target},@{ordinal}{no_name}{data}");
+            }
+        }
+        Flavor::Gnu => {
+            let forwarders_def = String::from("EXPORTS\n")
+                + entries
+                    .iter()
+                    .map(|entry| {
+                        let ForwardEntry {
+                            name,
+                            target,
+                            ordinal,
+                            ..
+                        } = entry;
+                        let target = target
+                            .as_ref()
+                            .map(|target| format!(" = \"{target}\""))
+                            .unwrap_or_default();
+                        let no_name = if entry.no_name { " NONAME" } else { "" };
+                        let data = if entry.data { " DATA" } else { "" };
+                        format!("  {name}{target} @{ordinal}{no_name}{data}\n")
+                    })
+                    .collect::<String>()
+                    .as_str();
+            std::fs::write(def_path, forwarders_def)
+                .map_err(|err| format!("Failed to write def file: {err}"))?;
+            println!("cargo:rustc-link-arg=-Wl,{}", def_path.display());
+            if target_arch() == "x86" {
+                println!("cargo:rustc-link-arg=-Wl,--enable-stdcall-fixup");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 把转发到跳转函数的导出名称写入 `OUT_DIR` 下的 `DYNAMIC_EXPORTS_FILE`，`#[hook]` 据此检查被替换的导出项是否已经排除。
+///
+/// 同一个 `build.rs` 中多次调用 `emit_dynamic` 时追加到同一个文件，不在 `build.rs` 中运行时不写入。
+fn record_dynamic_exports(entries: &[ForwardEntry]) -> Result<(), String> {
+    static STARTED: AtomicBool = AtomicBool::new(false);
+    let Ok(out_dir) = std::env::var("OUT_DIR") else {
+        return Ok(());
+    };
+    let append = STARTED.swap(true, Ordering::Relaxed);
+    let names: String = entries
+        .iter()
+        .filter(|entry| entry.target.is_some() && !entry.no_name)
+        .map(|entry| format!("{}\n", entry.name))
+        .collect();
+    std::fs::OpenOptions::new()
         .create(true)
         .write(true)
-        .truncate(true)
-        .open(version_lib_path)
-        .map_err(|err| format!("OpenOptions::open error: {err}"))?;
-    lib.write_to(&mut lib_file)
-        .map_err(|err| format!("ImportLibrary::write_to error: {err}"))?;
+        .append(append)
+        .truncate(!append)
+        .open(Path::new(&out_dir).join(DYNAMIC_EXPORTS_FILE))
+        .and_then(|mut file| std::io::Write::write_all(&mut file, names.as_bytes()))
+        .map_err(|err| format!("Failed to write {DYNAMIC_EXPORTS_FILE}: {err}"))
+}
 
-    println!("cargo:rustc-link-search={}", out_dir.display());
-    println!("cargo:rustc-link-lib=version_proxy");
+/// 构造 Import Library。不转发的导出项由代理 DLL 自己实现，不放进 Import Library，
+/// 这样忘记实现时会在链接时报错，而不是链接到代理 DLL 自身。
+fn import_library(
+    library_name: String,
+    entries: &[ForwardEntry],
+    machine: MachineType,
+    flavor: Flavor,
+) -> Result<ImportLibrary, String> {
+    let mut def = ModuleDef {
+        import_name: library_name,
+        ..Default::default()
+    };
+    for entry in entries.iter().filter(|entry| entry.target.is_some()) {
+        def.exports
+            .push(import_library_export(entry, machine, flavor)?);
+    }
+    Ok(ImportLibrary::from_def(def, machine, flavor))
+}
 
-    Ok(())
+/// 构造 Import Library 中的一项，见 [`forward_dll_pe::decorate::import_symbol`]。
+///
+/// MSVC 下 `implib` 根据 `name` 与 `symbol_name` 推断导入名称类型：两者相同且以 `_` 开头时为 NameNoPrefix，否则为 Name。
+/// MinGW 下 `implib` 在 x86 上总是去掉 `name` 的第一个字符作为查找名称，因此 `name` 需要是 `_` 加上查找名称，
+/// 这样已修饰名称的符号会多一个 `_`，但保证了加载时能找到正确的导出项。
+fn import_library_export(
+    entry: &ForwardEntry,
+    machine: MachineType,
+    flavor: Flavor,
+) -> Result<ShortExport, String> {
+    let x86 = machine == MachineType::I386;
+    let ImportSymbol { symbol, lookup } = import_symbol(&entry.name, x86);
+    let name = match flavor {
+        Flavor::Gnu if x86 => format!("_{lookup}"),
+        _ => symbol,
+    };
+    Ok(ShortExport {
+        symbol_name: name.clone(),
+        name,
+        ordinal: u16::try_from(entry.ordinal).map_err(|_| {
+            format!(
+                "Ordinal {} of {} is out of range",
+                entry.ordinal, entry.name
+            )
+        })?,
+        no_name: entry.no_name,
+        data: entry.data,
+        ..Default::default()
+    })
+}
+
+/// 取得 `DLL` 路径中不带扩展名的文件名，如 `C:\Windows\system32\winmm.dll` 对应 `winmm`。
+///
+/// 交叉编译时宿主机可能不是 Windows，因此这里同时按 `/` 与 `\` 分割路径，而不使用 `Path::file_stem`。
+fn dll_file_stem(dll_path: &str) -> String {
+    let file_name = dll_path.rsplit(['/', '\\']).next().unwrap_or(dll_path);
+    match file_name.rfind('.') {
+        Some(pos) if pos > 0 => file_name[..pos].to_string(),
+        _ => file_name.to_string(),
+    }
+}
+
+/// 为 Import Library 等生成的文件取一个在本次构建中不重复的名称，避免同一个 `build.rs` 中转发多个 `DLL` 时相互覆盖。
+fn unique_proxy_name(library_name: &str) -> String {
+    static USED_NAMES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    let mut used_names = USED_NAMES.lock().unwrap_or_else(|err| err.into_inner());
+    let mut proxy_name = format!("{library_name}_proxy");
+    let mut index = 1;
+    while used_names.contains(&proxy_name) {
+        index += 1;
+        proxy_name = format!("{library_name}_proxy_{index}");
+    }
+    used_names.push(proxy_name.clone());
+    proxy_name
+}
+
+/// 读取目标 `DLL` 的导出表，并确认目标 `DLL` 的机器类型与 Cargo 的编译目标一致。
+fn read_target_exports(dll_path: &str) -> Result<Vec<ExportItem>, String> {
+    let machine = target_machine()?;
+    let dll_machine =
+        forward_dll_pe::read_machine_from_path(dll_path).map_err(|err| err.to_string())?;
+    if dll_machine != machine as u16 {
+        return Err(format!(
+            "The machine type of {dll_path} ({dll_machine:#x}) does not match the target arch {} ({:#x})",
+            target_arch(),
+            machine as u16
+        ));
+    }
+    forward_dll_pe::read_exports_from_path(dll_path).map_err(|err| err.to_string())
+}
+
+/// 查询 CARGO_CFG_TARGET_ARCH 变量，即 Cargo 的编译目标架构。在 `build.rs` 中，`#[cfg(target_arch)]` 表示的是宿主机的架构，不能用来判断编译目标。
+fn target_arch() -> String {
+    std::env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_else(|_| std::env::consts::ARCH.to_string())
+}
+
+/// 根据 CARGO_CFG_TARGET_ENV 变量选择 MSVC 或 MinGW 的链接方式。
+fn target_flavor() -> Flavor {
+    let target_env = std::env::var("CARGO_CFG_TARGET_ENV").unwrap_or_else(|_| {
+        if cfg!(target_env = "gnu") {
+            "gnu".to_string()
+        } else {
+            "msvc".to_string()
+        }
+    });
+    if target_env == "gnu" {
+        Flavor::Gnu
+    } else {
+        Flavor::Msvc
+    }
+}
+
+/// 根据 Cargo 的编译目标架构选择 Import Library 的机器类型。
+fn target_machine() -> Result<MachineType, String> {
+    match target_arch().as_str() {
+        "x86_64" => Ok(MachineType::AMD64),
+        "x86" => Ok(MachineType::I386),
+        "aarch64" => Ok(MachineType::ARM64),
+        "arm64ec" => Err("Import libraries for arm64ec are not supported by implib".to_string()),
+        arch => Err(format!("Unsupported target arch: {arch}")),
+    }
 }
 
 /// 查询 OUT_DIR 变量，作为创建的 Import Library 存储路径。如果是在 doctest 的上下文中，是取不到 OUT_DIR 的。
@@ -364,37 +1490,171 @@ fn get_tmp_dir() -> PathBuf {
         })
 }
 
-fn get_dll_export_names(dll_path: &str) -> Result<Vec<ExportItem>, String> {
-    let dll_file = std::fs::read(dll_path).map_err(|err| format!("Failed to read file: {err}"))?;
-    let in_data = dll_file.as_slice();
-
-    let kind = object::FileKind::parse(in_data).map_err(|err| format!("Invalid file: {err}"))?;
-    let exports = match kind {
-        object::FileKind::Pe32 => PeFile32::parse(in_data)
-            .map_err(|err| format!("Invalid pe file: {err}"))?
-            .export_table()
-            .map_err(|err| format!("Invalid pe file: {err}"))?
-            .ok_or_else(|| "No export table".to_string())?
-            .exports(),
-        object::FileKind::Pe64 => PeFile64::parse(in_data)
-            .map_err(|err| format!("Invalid pe file: {err}"))?
-            .export_table()
-            .map_err(|err| format!("Invalid pe file: {err}"))?
-            .ok_or_else(|| "No export table".to_string())?
-            .exports(),
-        _ => return Err("Invalid file".to_string()),
-    }
-    .map_err(|err| format!("Invalid file: {err}"))?;
-
-    let mut export_list = Vec::new();
-    for export_item in exports {
-        let ordinal = export_item.ordinal;
-        let name = export_item
-            .name
-            .map(String::from_utf8_lossy)
-            .map(String::from);
-        let item = ExportItem { name, ordinal };
-        export_list.push(item);
-    }
-    Ok(export_list)
+#[cfg(test)]
+mod tests {
+    use object::{read::archive::ArchiveFile, Object, ObjectSection, ObjectSymbol};
+
+    use super::*;
+
+    #[test]
+    fn remapped_ordinals_forward_to_the_original_ordinal() {
+        let config = ForwardConfig::new()
+            .target("C:\\App\\core.dll")
+            .target("C:\\App\\core_ext.dll")
+            .remap_ordinals(true);
+        let sets = vec![
+            vec![ExportItem::code(1, Some("Init")), ExportItem::code(2, None)],
+            vec![
+                ExportItem::code(1, Some("Start")),
+                ExportItem::code(2, None),
+            ],
+        ];
+        let options = MergeOptions {
+            remap_ordinals: true,
+            ..Default::default()
+        };
+        let exports = merge_exports(&sets, &options).unwrap();
+        let (entries, chained) = config
+            .forward_entries(&exports, &ExportFilter::default())
+            .unwrap();
+        assert!(chained.is_empty());
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.name.as_str(),
+                    entry.target.as_deref(),
+                    entry.ordinal,
+                    entry.no_name,
+                )
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("Init", Some("C:\\App\\core.Init"), 1, false),
+                ("forward_dll_anonymous_1", Some("C:\\App\\core.#2"), 2, true),
+                ("Start", Some("C:\\App\\core_ext.Start"), 3, false),
+                (
+                    "forward_dll_anonymous_2",
+                    Some("C:\\App\\core_ext.#2"),
+                    4,
+                    true
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn target_dll_main_is_not_forwarded() {
+        let config = ForwardConfig::new().target("C:\\Windows\\System32\\xinput1_3.dll");
+        let sets = vec![vec![
+            ExportItem::code(1, Some("DllMain")),
+            ExportItem::code(2, Some("XInputGetState")),
+        ]];
+        let exports = merge_exports(&sets, &MergeOptions::default()).unwrap();
+        let (entries, _) = config
+            .forward_entries(&exports, &ExportFilter::default())
+            .unwrap();
+        let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["XInputGetState"]);
+    }
+
+    fn x86_entries() -> Vec<ForwardEntry> {
+        ["Func", "_Func@12"]
+            .iter()
+            .enumerate()
+            .map(|(index, name)| ForwardEntry {
+                name: name.to_string(),
+                target: Some(format!("C:\\App\\test.{name}")),
+                ordinal: index as u32 + 1,
+                no_name: false,
+                data: false,
+            })
+            .collect()
+    }
+
+    fn x86_short_exports(flavor: Flavor) -> Vec<(String, String)> {
+        x86_entries()
+            .iter()
+            .map(|entry| import_library_export(entry, MachineType::I386, flavor).unwrap())
+            .map(|export| (export.name, export.symbol_name))
+            .collect()
+    }
+
+    /// 生成 Import Library，返回其中每个成员的内容。
+    fn x86_library_members(flavor: Flavor) -> Vec<Vec<u8>> {
+        let lib = import_library(
+            "test".to_string(),
+            &x86_entries(),
+            MachineType::I386,
+            flavor,
+        )
+        .unwrap();
+        let mut data = std::io::Cursor::new(Vec::new());
+        lib.write_to(&mut data).unwrap();
+        let data = data.into_inner();
+        let archive = ArchiveFile::parse(data.as_slice()).unwrap();
+        archive
+            .members()
+            .map(|member| member.unwrap().data(data.as_slice()).unwrap().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn x86_msvc_import_library() {
+        let owned = |name: &str| (name.to_string(), name.to_string());
+        assert_eq!(
+            x86_short_exports(Flavor::Msvc),
+            [owned("_Func"), owned("_Func@12")]
+        );
+
+        // 短导入头之后依次是符号名称与 DLL 名称，名称类型位于类型字段的第 2 ~ 4 位。
+        let imports: Vec<_> = x86_library_members(Flavor::Msvc)
+            .iter()
+            .filter(|member| member.starts_with(&[0, 0, 0xff, 0xff]))
+            .map(|member| {
+                let name_type = u16::from_le_bytes([member[18], member[19]]) >> 2 & 7;
+                let symbol = member[20..].split(|byte| *byte == 0).next().unwrap();
+                (String::from_utf8_lossy(symbol).into_owned(), name_type)
+            })
+            .collect();
+        // `Func` 为 NameNoPrefix，加载时去掉 `_` 按 `Func` 查找；`_Func@12` 为 Name，按原样查找。
+        assert_eq!(
+            imports,
+            [("_Func".to_string(), 2), ("_Func@12".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn x86_gnu_import_library() {
+        let owned = |name: &str| (name.to_string(), name.to_string());
+        assert_eq!(
+            x86_short_exports(Flavor::Gnu),
+            [owned("_Func"), owned("__Func@12")]
+        );
+
+        // implib 去掉名称的第一个字符作为查找名称写入 `.idata$6`。
+        let mut imports: Vec<_> = x86_library_members(Flavor::Gnu)
+            .iter()
+            .filter_map(|member| {
+                let file = object::File::parse(member.as_slice()).unwrap();
+                let hint_name = file.section_by_name(".idata$6")?.data().unwrap().to_vec();
+                let lookup = hint_name[2..].split(|byte| *byte == 0).next().unwrap();
+                let symbol = file
+                    .symbols()
+                    .filter_map(|symbol| symbol.name().ok().map(str::to_string))
+                    .find(|name| name.starts_with("__imp_"))?;
+                Some((symbol, String::from_utf8_lossy(lookup).into_owned()))
+            })
+            .collect();
+        imports.sort();
+        assert_eq!(
+            imports,
+            [
+                ("__imp__Func".to_string(), "Func".to_string()),
+                ("__imp___Func@12".to_string(), "_Func@12".to_string())
+            ]
+        );
+    }
 }
@@ -0,0 +1,171 @@
+//! 运行时目标 DLL 路径的解析。
+//!
+//! 路径中可以使用以下占位符，在加载目标 DLL 时展开：
+//!
+//! - `{system}`：系统目录，即 `GetSystemDirectoryW` 的返回值，如 `C:\Windows\System32`。
+//! - `{syswow64}`：WOW64 系统目录，即 `GetSystemWow64DirectoryW` 的返回值，如 `C:\Windows\SysWOW64`，32 位系统上不存在。
+//! - `{proxy_dir}`：代理 DLL 所在的目录。
+//! - `{env:VAR}`：环境变量 `VAR` 的值。
+//!
+//! ```rust
+//! use forward_dll::TargetPath;
+//!
+//! static VERSION: TargetPath =
+//!     TargetPath::candidates(&["{proxy_dir}\\version_orig.dll", "{system}\\version.dll"]);
+//! assert_eq!(VERSION.paths().len(), 2);
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use windows_sys::Win32::Foundation::HMODULE;
+
+use crate::{
+    utils::{
+        current_module, free_library, load_library_with_options, module_file_name,
+        system_directory, system_wow64_directory, LoadOptions,
+    },
+    ForwardError, ForwardResult,
+};
+
+/// 目标 DLL 的路径，可以包含占位符，也可以是按顺序尝试的多个候选路径。
+#[derive(Debug, Clone, Copy)]
+pub struct TargetPath {
+    candidates: Candidates,
+    options: LoadOptions,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Candidates {
+    One(&'static str),
+    Many(&'static [&'static str]),
+}
+
+impl TargetPath {
+    /// 只有一个路径。
+    pub const fn new(path: &'static str) -> Self {
+        Self {
+            candidates: Candidates::One(path),
+            options: LoadOptions::new(),
+        }
+    }
+
+    /// 按顺序尝试的多个候选路径，使用第一个能成功加载的路径。
+    ///
+    /// `paths` 不能为空，为空时 panic，在 `static` 或 `const` 中使用时会产生编译错误。
+    ///
+    /// ```rust,compile_fail
+    /// use forward_dll::TargetPath;
+    ///
+    /// static EMPTY: TargetPath = TargetPath::candidates(&[]);
+    /// ```
+    pub const fn candidates(paths: &'static [&'static str]) -> Self {
+        assert!(
+            !paths.is_empty(),
+            "TargetPath needs at least one candidate path"
+        );
+        Self {
+            candidates: Candidates::Many(paths),
+            options: LoadOptions::new(),
+        }
+    }
+
+    /// 加载时使用的选项，用于控制目标 DLL 及其依赖项的搜索顺序。
+    pub const fn with_options(mut self, options: LoadOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// 未展开占位符的候选路径。
+    pub fn paths(&self) -> &[&'static str] {
+        match &self.candidates {
+            Candidates::One(path) => std::slice::from_ref(path),
+            Candidates::Many(paths) => paths,
+        }
+    }
+
+    /// 依次展开并加载候选路径，返回第一个加载成功的模块句柄。都失败时返回最后一个错误。
+    ///
+    /// 候选路径指向代理 DLL 自己时（如代理 DLL 名为 `version.dll`，路径也只写了 `version.dll`），
+    /// 转发会无限循环，因此跳过这个候选路径，并在最后尝试系统目录下的同名 DLL，仍然失败时返回 `SelfReference`。
+    pub fn load(&self) -> ForwardResult<HMODULE> {
+        let proxy_module = current_module()?;
+        let mut last_error = ForwardError::InvalidPath(String::new());
+        let mut self_reference = None;
+        for path in self.paths() {
+            let path = match expand_path(path) {
+                Ok(path) => path,
+                Err(err) => {
+                    last_error = err;
+                    continue;
+                }
+            };
+            match self.load_candidate(&path, proxy_module) {
+                Ok(module_handle) => return Ok(module_handle),
+                Err(ForwardError::SelfReference) => {
+                    self_reference = path.file_name().map(|name| name.to_owned());
+                }
+                Err(err) => last_error = err,
+            }
+        }
+        if let Some(file_name) = self_reference {
+            let path = system_directory()?.join(file_name);
+            return self
+                .load_candidate(&path, proxy_module)
+                .map_err(|_| ForwardError::SelfReference);
+        }
+        Err(last_error)
+    }
+
+    fn load_candidate(&self, path: &Path, proxy_module: HMODULE) -> ForwardResult<HMODULE> {
+        let module_handle = load_library_with_options(path, &self.options)?;
+        if module_handle == proxy_module {
+            // 加载时增加了代理 DLL 自己的引用计数，这里减回去。
+            free_library(module_handle);
+            return Err(ForwardError::SelfReference);
+        }
+        Ok(module_handle)
+    }
+}
+
+impl From<&'static str> for TargetPath {
+    fn from(path: &'static str) -> Self {
+        Self::new(path)
+    }
+}
+
+impl std::fmt::Display for TargetPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.paths().join(" | "))
+    }
+}
+
+/// 展开路径中的占位符，无法识别的占位符或不存在的环境变量返回 `InvalidPath`。
+pub fn expand_path(path: &str) -> ForwardResult<PathBuf> {
+    let mut expanded = std::ffi::OsString::new();
+    let mut rest = path;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| ForwardError::InvalidPath(path.to_string()))?;
+        expanded.push(&rest[..start]);
+        let placeholder = &rest[start + 1..end];
+        let value = match placeholder {
+            "system" => system_directory()?.into_os_string(),
+            "syswow64" => system_wow64_directory()?.into_os_string(),
+            "proxy_dir" => module_file_name(current_module()?)?
+                .parent()
+                .map(|dir| dir.as_os_str().to_owned())
+                .unwrap_or_default(),
+            _ => match placeholder.strip_prefix("env:") {
+                Some(name) => std::env::var_os(name)
+                    .ok_or_else(|| ForwardError::InvalidPath(path.to_string()))?,
+                None => return Err(ForwardError::InvalidPath(path.to_string())),
+            },
+        };
+        expanded.push(value);
+        rest = &rest[end + 1..];
+    }
+    expanded.push(rest);
+    Ok(PathBuf::from(expanded))
+}
@@ -0,0 +1,118 @@
+//! 跳转函数的调用跟踪，用于观察宿主程序调用了哪些导出函数，以及调用的顺序。
+//!
+//! 使用 `#[forward(trace)]` 时，每个跳转函数在跳转到目标函数之前，都会把导出项名称、线程 ID 与前几个参数交给 `TraceSink`。
+//! 没有调用 `set_sink` 时，记录会写入代理 DLL 旁边的 `<代理 DLL 文件名>.trace.log` 中。
+
+use std::{
+    cell::Cell,
+    fs::File,
+    io::Write,
+    path::Path,
+    sync::{Mutex, OnceLock, RwLock},
+};
+
+use windows_sys::Win32::System::Threading::GetCurrentThreadId;
+
+use crate::utils::{current_module, module_file_name};
+
+/// 一次导出函数的调用。
+#[derive(Debug, Clone, Copy)]
+pub struct TraceEvent<'a> {
+    /// 导出项在目标 DLL 中的名称，仅以序号导出时为 `#` 加序号。
+    pub name: &'a str,
+    /// 调用者的线程 ID。
+    pub thread_id: u32,
+    /// 前 4 个参数。x64 上为 `rcx`、`rdx`、`r8`、`r9`，x86 上为栈上的前 4 个值。函数的参数不足 4 个时，多出的值没有意义。
+    pub arguments: [usize; 4],
+}
+
+/// 接收调用记录。调用发生在宿主程序的线程中，实现应当尽快返回，并且不能调用被跟踪的导出函数。
+pub trait TraceSink: Send + Sync {
+    fn record(&self, event: &TraceEvent);
+}
+
+static SINK: RwLock<Option<Box<dyn TraceSink>>> = RwLock::new(None);
+static DEFAULT_SINK: OnceLock<Option<FileSink>> = OnceLock::new();
+
+thread_local! {
+    /// 是否正在记录，防止 `TraceSink` 间接调用被跟踪的导出函数时无限递归。
+    static IN_TRACE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 设置接收调用记录的 `TraceSink`，替换默认的 `FileSink`。
+pub fn set_sink<S: TraceSink + 'static>(sink: S) {
+    *SINK.write().unwrap_or_else(|err| err.into_inner()) = Some(Box::new(sink));
+}
+
+/// 把调用记录交给当前的 `TraceSink`。
+pub fn record(event: &TraceEvent) {
+    if IN_TRACE.with(|in_trace| in_trace.replace(true)) {
+        return;
+    }
+    let sink = SINK.read().unwrap_or_else(|err| err.into_inner());
+    match sink.as_deref() {
+        Some(sink) => sink.record(event),
+        None => {
+            if let Some(sink) = DEFAULT_SINK.get_or_init(|| FileSink::next_to_proxy().ok()) {
+                sink.record(event);
+            }
+        }
+    }
+    IN_TRACE.with(|in_trace| in_trace.set(false));
+}
+
+/// 由跳转函数调用，`frame` 指向跳转函数入口处保存的寄存器。
+///
+/// # Safety
+///
+/// `frame` 必须是 `define_function!` 生成的跳转函数入口处保存寄存器的位置。
+#[doc(hidden)]
+pub unsafe fn record_frame(name: &str, frame: *const usize) {
+    // x64 上依次保存了 rcx、rdx、r8、r9、r10、r11，`frame` 指向最后保存的 r11。
+    #[cfg(target_arch = "x86_64")]
+    let arguments = [*frame.add(5), *frame.add(4), *frame.add(3), *frame.add(2)];
+    // x86 上 `frame` 直接指向栈上的第一个参数。
+    #[cfg(not(target_arch = "x86_64"))]
+    let arguments = [*frame, *frame.add(1), *frame.add(2), *frame.add(3)];
+    record(&TraceEvent {
+        name,
+        thread_id: GetCurrentThreadId(),
+        arguments,
+    });
+}
+
+/// 把调用记录逐行写入文件的 `TraceSink`，每行为线程 ID、导出项名称与参数。
+pub struct FileSink {
+    file: Mutex<File>,
+}
+
+impl FileSink {
+    /// 创建或清空 `path`。
+    pub fn new<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Ok(Self {
+            file: Mutex::new(File::create(path)?),
+        })
+    }
+
+    /// 写入代理 DLL 旁边的 `<代理 DLL 文件名>.trace.log`。
+    pub fn next_to_proxy() -> std::io::Result<Self> {
+        let proxy_path = current_module()
+            .and_then(module_file_name)
+            .map_err(std::io::Error::other)?;
+        let mut path = proxy_path.into_os_string();
+        path.push(".trace.log");
+        Self::new(path)
+    }
+}
+
+impl TraceSink for FileSink {
+    fn record(&self, event: &TraceEvent) {
+        let mut file = self.file.lock().unwrap_or_else(|err| err.into_inner());
+        let [a0, a1, a2, a3] = event.arguments;
+        let _ = writeln!(
+            file,
+            "{} {} {:#x} {:#x} {:#x} {:#x}",
+            event.thread_id, event.name, a0, a1, a2, a3
+        );
+    }
+}
@@ -1,10 +1,20 @@
-use std::ffi::CString;
+use std::{
+    ffi::{CString, OsStr, OsString},
+    path::PathBuf,
+};
 
 use windows_sys::Win32::{
-    Foundation::{GetLastError, HMODULE},
-    System::LibraryLoader::{
-        FreeLibrary, GetModuleHandleExA, GetProcAddress, LoadLibraryA,
-        GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+    Foundation::{GetLastError, ERROR_INSUFFICIENT_BUFFER, HMODULE},
+    System::{
+        LibraryLoader::{
+            FreeLibrary, GetModuleFileNameW, GetModuleHandleExA, GetModuleHandleExW,
+            GetProcAddress, LoadLibraryExW, GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+            GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT, LOAD_LIBRARY_FLAGS,
+            LOAD_LIBRARY_SEARCH_APPLICATION_DIR, LOAD_LIBRARY_SEARCH_DEFAULT_DIRS,
+            LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR, LOAD_LIBRARY_SEARCH_SYSTEM32,
+            LOAD_LIBRARY_SEARCH_USER_DIRS, LOAD_WITH_ALTERED_SEARCH_PATH,
+        },
+        SystemInformation::{GetSystemDirectoryW, GetSystemWow64DirectoryW},
     },
 };
 
@@ -28,28 +38,195 @@ pub fn load_library_by_handle(inst: HMODULE) -> ForwardResult<HMODULE> {
     Ok(module_handle)
 }
 
-/// LoadLibraryA 的包装。
-pub fn load_library(lib_filename: &str) -> ForwardResult<HMODULE> {
-    let module_name = CString::new(lib_filename).map_err(ForwardError::StringError)?;
-    let module_handle = unsafe { LoadLibraryA(module_name.as_ptr() as *const u8) };
+/// LoadLibraryExW 的选项，用于控制目标 DLL 及其依赖项的搜索顺序，默认与 LoadLibrary 相同。
+///
+/// ```rust
+/// use forward_dll::utils::LoadOptions;
+///
+/// let options = LoadOptions::new().search_system32().search_dll_load_dir();
+/// assert_eq!(options.bits(), 0x800 | 0x100);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoadOptions {
+    flags: LOAD_LIBRARY_FLAGS,
+}
+
+impl LoadOptions {
+    pub const fn new() -> Self {
+        Self { flags: 0 }
+    }
+
+    /// 直接添加 LoadLibraryExW 的 `dwFlags`，如 `LOAD_LIBRARY_SEARCH_*`。
+    pub const fn flags(mut self, flags: LOAD_LIBRARY_FLAGS) -> Self {
+        self.flags |= flags;
+        self
+    }
+
+    /// `LOAD_LIBRARY_SEARCH_SYSTEM32`：搜索系统目录。
+    pub const fn search_system32(self) -> Self {
+        self.flags(LOAD_LIBRARY_SEARCH_SYSTEM32)
+    }
+
+    /// `LOAD_LIBRARY_SEARCH_APPLICATION_DIR`：搜索应用程序所在的目录。
+    pub const fn search_application_dir(self) -> Self {
+        self.flags(LOAD_LIBRARY_SEARCH_APPLICATION_DIR)
+    }
+
+    /// `LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR`：搜索被加载的 DLL 所在的目录，路径必须是绝对路径。
+    pub const fn search_dll_load_dir(self) -> Self {
+        self.flags(LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR)
+    }
+
+    /// `LOAD_LIBRARY_SEARCH_USER_DIRS`：搜索通过 AddDllDirectory 添加的目录。
+    pub const fn search_user_dirs(self) -> Self {
+        self.flags(LOAD_LIBRARY_SEARCH_USER_DIRS)
+    }
+
+    /// `LOAD_LIBRARY_SEARCH_DEFAULT_DIRS`：相当于同时使用应用程序目录、系统目录与用户目录。
+    pub const fn search_default_dirs(self) -> Self {
+        self.flags(LOAD_LIBRARY_SEARCH_DEFAULT_DIRS)
+    }
+
+    /// `LOAD_WITH_ALTERED_SEARCH_PATH`：从被加载的 DLL 所在的目录开始搜索依赖项，不能与 `search_*` 同时使用。
+    pub const fn altered_search_path(self) -> Self {
+        self.flags(LOAD_WITH_ALTERED_SEARCH_PATH)
+    }
+
+    /// 传给 LoadLibraryExW 的 `dwFlags`。
+    pub const fn bits(&self) -> LOAD_LIBRARY_FLAGS {
+        self.flags
+    }
+}
+
+/// LoadLibraryExW 的包装，路径以 UTF-16 传递，可以包含非 ASCII 字符。
+pub fn load_library<P: AsRef<OsStr>>(lib_filename: P) -> ForwardResult<HMODULE> {
+    load_library_with_options(lib_filename, &LoadOptions::new())
+}
+
+/// 同 `load_library`，通过 `options` 控制搜索顺序。
+pub fn load_library_with_options<P: AsRef<OsStr>>(
+    lib_filename: P,
+    options: &LoadOptions,
+) -> ForwardResult<HMODULE> {
+    let module_name = to_wide_string(lib_filename.as_ref())?;
+    let module_handle = unsafe { LoadLibraryExW(module_name.as_ptr(), 0, options.bits()) };
     if module_handle == 0 {
-        return Err(ForwardError::Win32Error("LoadLibraryA", unsafe {
+        return Err(ForwardError::Win32Error("LoadLibraryExW", unsafe {
             GetLastError()
         }));
     }
     Ok(module_handle)
 }
 
+/// 取得代理 DLL 自身（即 forward-dll 被链接到的模块）的模块句柄，不增加引用计数。
+pub fn current_module() -> ForwardResult<HMODULE> {
+    let mut module_handle = 0;
+    let success = unsafe {
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+            current_module as *const () as *const u16,
+            &mut module_handle,
+        )
+    } != 0;
+    if !success {
+        return Err(ForwardError::Win32Error("GetModuleHandleExW", unsafe {
+            GetLastError()
+        }));
+    }
+    Ok(module_handle)
+}
+
+/// GetModuleFileNameW 的包装，取得模块的完整路径。
+pub fn module_file_name(inst: HMODULE) -> ForwardResult<PathBuf> {
+    let mut buffer = vec![0u16; 260];
+    loop {
+        let len = unsafe { GetModuleFileNameW(inst, buffer.as_mut_ptr(), buffer.len() as u32) };
+        if len == 0 {
+            return Err(ForwardError::Win32Error("GetModuleFileNameW", unsafe {
+                GetLastError()
+            }));
+        }
+        // 缓冲区不够大时返回值等于缓冲区大小，并设置 ERROR_INSUFFICIENT_BUFFER。
+        if (len as usize) < buffer.len() || unsafe { GetLastError() } != ERROR_INSUFFICIENT_BUFFER {
+            buffer.truncate(len as usize);
+            return Ok(PathBuf::from(from_wide_string(&buffer)));
+        }
+        buffer.resize(buffer.len() * 2, 0);
+    }
+}
+
+/// GetSystemDirectoryW 的包装，通常为 `C:\Windows\System32`。
+pub fn system_directory() -> ForwardResult<PathBuf> {
+    read_directory("GetSystemDirectoryW", |buffer, size| unsafe {
+        GetSystemDirectoryW(buffer, size)
+    })
+}
+
+/// GetSystemWow64DirectoryW 的包装，通常为 `C:\Windows\SysWOW64`，32 位系统上会返回错误。
+pub fn system_wow64_directory() -> ForwardResult<PathBuf> {
+    read_directory("GetSystemWow64DirectoryW", |buffer, size| unsafe {
+        GetSystemWow64DirectoryW(buffer, size)
+    })
+}
+
+/// 调用 GetSystemDirectoryW 这类函数：缓冲区不够大时返回所需的大小（包含结尾的 0），失败时返回 0。
+fn read_directory(
+    func_name: &'static str,
+    read: impl Fn(*mut u16, u32) -> u32,
+) -> ForwardResult<PathBuf> {
+    let mut buffer = vec![0u16; 260];
+    loop {
+        let len = read(buffer.as_mut_ptr(), buffer.len() as u32) as usize;
+        if len == 0 {
+            return Err(ForwardError::Win32Error(func_name, unsafe {
+                GetLastError()
+            }));
+        }
+        if len < buffer.len() {
+            buffer.truncate(len);
+            return Ok(PathBuf::from(from_wide_string(&buffer)));
+        }
+        buffer.resize(len, 0);
+    }
+}
+
+/// 转换为以 0 结尾的 UTF-16 字符串。
+fn to_wide_string(value: &OsStr) -> ForwardResult<Vec<u16>> {
+    #[cfg(windows)]
+    let wide: Vec<u16> = std::os::windows::ffi::OsStrExt::encode_wide(value).collect();
+    #[cfg(not(windows))]
+    let wide: Vec<u16> = value.to_string_lossy().encode_utf16().collect();
+    if wide.contains(&0) {
+        return Err(ForwardError::InvalidPath(
+            value.to_string_lossy().into_owned(),
+        ));
+    }
+    Ok(wide.into_iter().chain(Some(0)).collect())
+}
+
+fn from_wide_string(value: &[u16]) -> OsString {
+    #[cfg(windows)]
+    return std::os::windows::ffi::OsStringExt::from_wide(value);
+    #[cfg(not(windows))]
+    return OsString::from(String::from_utf16_lossy(value));
+}
+
 /// FreeLibrary 的包装。
 pub fn free_library(inst: HMODULE) {
     unsafe { FreeLibrary(inst) };
 }
 
-/// 取得指定函数名称的函数地址。
+/// 取得指定函数名称的函数地址，导出表中的名称只能是 ANSI 字符串。名称为 `#` 加数字时（如 `#3`），按序号查找。
 pub fn get_proc_address_by_module(
     inst: HMODULE,
     proc_name: &str,
 ) -> ForwardResult<unsafe extern "system" fn() -> isize> {
+    if let Some(ordinal) = proc_name
+        .strip_prefix('#')
+        .and_then(|ordinal| ordinal.parse().ok())
+    {
+        return get_proc_address_by_ordinal(inst, ordinal);
+    }
     let proc_name = CString::new(proc_name).map_err(ForwardError::StringError)?;
     unsafe {
         GetProcAddress(inst, proc_name.as_ptr() as *const u8)
@@ -57,13 +234,33 @@ pub fn get_proc_address_by_module(
     }
 }
 
+/// 取得指定序号的函数地址。
+pub fn get_proc_address_by_ordinal(
+    inst: HMODULE,
+    ordinal: u16,
+) -> ForwardResult<unsafe extern "system" fn() -> isize> {
+    // 序号放在低 16 位、高位为 0 的指针即表示按序号查找，即 MAKEINTRESOURCEA。
+    unsafe {
+        GetProcAddress(inst, ordinal as usize as *const u8)
+            .ok_or_else(|| ForwardError::Win32Error("GetProcAddress", GetLastError()))
+    }
+}
+
 pub struct ForeignLibrary {
     pub module_handle: HMODULE,
 }
 
 impl ForeignLibrary {
-    pub fn new(lib_name: &str) -> ForwardResult<Self> {
-        let module_handle = load_library(lib_name)?;
+    pub fn new<P: AsRef<OsStr>>(lib_name: P) -> ForwardResult<Self> {
+        Self::with_options(lib_name, &LoadOptions::new())
+    }
+
+    /// 同 `new`，通过 `options` 控制目标 DLL 及其依赖项的搜索顺序。
+    pub fn with_options<P: AsRef<OsStr>>(
+        lib_name: P,
+        options: &LoadOptions,
+    ) -> ForwardResult<Self> {
+        let module_handle = load_library_with_options(lib_name, options)?;
         Ok(Self { module_handle })
     }
 